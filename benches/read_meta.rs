@@ -0,0 +1,118 @@
+//! Compares decoding metas field by field from an unbuffered file (each field a separate read)
+//! against `MsrfReader`, which decodes them from its read-ahead buffer. Both read payloads into
+//! a single reused buffer.
+
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use msrf::{
+    ConstAssignedId, IntoMetadata,
+    codec::{IntoData, RawDeserialiser, RawSerialiser, v0},
+    error::{ErrorKind, IoError, ParserError},
+    io::SizedValue,
+    reader::MsrfReaderBuilder,
+    writer::MsrfWriterBuilder,
+};
+
+const RECORDS: u64 = 100_000;
+// Magic bytes: 4, Version: 2, Guard: 1
+const HEADER_LEN: usize = 7;
+
+#[derive(Debug)]
+struct Value(u64);
+
+impl ConstAssignedId for Value {
+    const TYPE_ID: u64 = 1;
+}
+
+impl<S> SizedValue<S> for Value {
+    fn encoded_len(&self, _ser: &S) -> usize {
+        8
+    }
+}
+
+impl<S: RawSerialiser> IntoMetadata<S> for Value {}
+
+impl<S: RawSerialiser, W: Write> IntoData<S, W> for Value {
+    fn encode_into(
+        &self,
+        wtr: &mut W,
+        _ser: &S,
+        _source_id: u64,
+    ) -> Result<(), IoError<ParserError>> {
+        wtr.write_all(&self.0.to_le_bytes())?;
+        Ok(())
+    }
+}
+
+fn write_stream() -> PathBuf {
+    let path = std::env::temp_dir().join("msrf-bench-read-meta.msrf");
+    let mut wtr = MsrfWriterBuilder::new()
+        .version(0)
+        .build(std::io::BufWriter::new(
+            File::create(&path).expect("failed to create"),
+        ))
+        .expect("unsupported version")
+        .initialise()
+        .expect("failed to initialise");
+    for value in 0..RECORDS {
+        wtr.write_record(Value(value), 1).expect("failed write");
+    }
+    wtr.finish().expect("failed finish");
+    path
+}
+
+// The path taken before the read-ahead buffer
+fn read_field_by_field(path: &Path) -> u64 {
+    let mut rdr = File::open(path).expect("failed to open");
+    let des = v0::Deserialiser::default();
+    let mut buf = [0; HEADER_LEN];
+    rdr.read_exact(&mut buf).expect("failed read");
+
+    // Reused across records, as by `read_record_with`
+    let mut payload = Vec::new();
+    let mut records = 0;
+    loop {
+        let meta = des.read_meta(&mut rdr).expect("failed read");
+        if meta.is_eos() {
+            return records;
+        }
+        payload.resize(meta.len() as usize + 1, 0); // Including the guard
+        rdr.read_exact(&mut payload).expect("failed read");
+        records += 1;
+    }
+}
+
+fn read_buffered(path: &Path) -> u64 {
+    let mut rdr = MsrfReaderBuilder::new()
+        .build(File::open(path).expect("failed to open"))
+        .expect("failed to build");
+    loop {
+        match rdr.read_record_with(|_, payload| payload.len()) {
+            Ok(_) => {}
+            Err(e) if e.kind() == ErrorKind::IsEos => return rdr.records(),
+            Err(e) => panic!("failed read: {e}"),
+        }
+    }
+}
+
+fn read_meta(c: &mut Criterion) {
+    let path = write_stream();
+    let mut group = c.benchmark_group("read_meta");
+    group.sample_size(10);
+    group.bench_function("field_by_field", |b| {
+        b.iter(|| assert_eq!(read_field_by_field(&path), RECORDS));
+    });
+    group.bench_function("buffered", |b| {
+        b.iter(|| assert_eq!(read_buffered(&path), RECORDS));
+    });
+    group.finish();
+    let _ = std::fs::remove_file(path);
+}
+
+criterion_group!(benches, read_meta);
+criterion_main!(benches);
@@ -10,6 +10,27 @@ use crate::{
 
 mod v0;
 
+/// Limits applied while reading, guarding against untrusted input. All limits are disabled by
+/// default.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DesOptions {
+    max_name_len: Option<usize>,
+}
+
+impl DesOptions {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bytes per source name, see [`DesError::NameLength`].
+    #[must_use]
+    pub fn max_name_len(mut self, max_name_len: usize) -> Self {
+        self.max_name_len = Some(max_name_len);
+        self
+    }
+}
+
 // TODO: Accept a shared configuration type (Options)
 pub trait RawSerialiser {
     fn write_source_add<W: Write>(&self, rec: &SourceAdd, wtr: W) -> Result<(), IoError<DesError>>;
@@ -120,9 +141,13 @@ impl RawDeserialiser for AnyDeserialiser {
 
 impl AnyDeserialiser {
     pub fn new(version: Version) -> AnyDeserialiser {
+        AnyDeserialiser::with_options(version, DesOptions::default())
+    }
+
+    pub fn with_options(version: Version, options: DesOptions) -> AnyDeserialiser {
         assert_eq!(0, MSRF_EXT_VERSION);
         match version.get() {
-            0 => AnyDeserialiser::V0(Deserialiser),
+            0 => AnyDeserialiser::V0(Deserialiser::from(options)),
             _ => unreachable!(),
         }
     }
@@ -2,7 +2,7 @@ use std::io::Read;
 
 use crate::{
     SourceAdd, SourceRemove,
-    codec::{RawDeserialiser, RawSerialiser},
+    codec::{DesOptions, RawDeserialiser, RawSerialiser},
     error::DesError,
 };
 use msrf::{IntoMetadata, error::IoError, io::SizedValue};
@@ -38,14 +38,30 @@ impl RawSerialiser for Serialiser {
 }
 
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
-pub struct Deserialiser;
+pub struct Deserialiser {
+    options: DesOptions,
+}
 
 impl RawDeserialiser for Deserialiser {
     fn read_source_add<R: Read>(&self, mut rdr: R) -> Result<SourceAdd, IoError<DesError>> {
         let mut buf = [0; SOURCE_ADD_LEN];
         rdr.read_exact(&mut buf)?;
-        let mut name_buf = String::new();
-        rdr.read_to_string(&mut name_buf)?;
+        let mut name_buf = Vec::new();
+        match self.options.max_name_len {
+            Some(max) => {
+                // Read one past the limit to distinguish a name of exactly `max` bytes, checked
+                // before decoding as the limit may split a character
+                rdr.take(max as u64 + 1).read_to_end(&mut name_buf)?;
+                if name_buf.len() > max {
+                    return Err(IoError::Parser(DesError::NameLength(name_buf.len())));
+                }
+            }
+            None => {
+                rdr.read_to_end(&mut name_buf)?;
+            }
+        }
+        let name_buf = String::from_utf8(name_buf)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
 
         Ok(SourceAdd {
             id: u16::from_le_bytes(*buf[..2].first_chunk().unwrap()), // SAFETY: [u8; 2] == size_of<u16>()
@@ -64,6 +80,12 @@ impl RawDeserialiser for Deserialiser {
     }
 }
 
+impl From<DesOptions> for Deserialiser {
+    fn from(options: DesOptions) -> Self {
+        Deserialiser { options }
+    }
+}
+
 impl SizedValue<Serialiser> for SourceAdd {
     fn encoded_len(&self, _ser: &Serialiser) -> usize {
         // ID: u16 + Version: u16 + Name: Variable
@@ -100,7 +122,7 @@ mod test {
         };
 
         let ser = Serialiser;
-        let des = Deserialiser;
+        let des = Deserialiser::default();
 
         let mut buf = [0u8; 14];
         ser.write_source_add(&ref_source_add.clone(), buf.as_mut_slice())
@@ -119,7 +141,7 @@ mod test {
         const REF_SOURCE_REMOVE: SourceRemove = SourceRemove { id: 32 };
 
         let ser = Serialiser;
-        let des = Deserialiser;
+        let des = Deserialiser::default();
 
         let mut buf = [0u8; 2];
         ser.write_source_remove(&REF_SOURCE_REMOVE, buf.as_mut_slice())
@@ -131,4 +153,37 @@ mod test {
         assert_eq!(rec, REF_SOURCE_REMOVE);
         assert_eq!(rdr.into_inner().position(), 2);
     }
+
+    #[test]
+    fn des_source_add_name_limit() {
+        let mut buf = Vec::new();
+        let rec = SourceAdd::new(32, 1, "pxls.space");
+        Serialiser
+            .write_source_add(&rec, &mut buf)
+            .expect("failed ser");
+
+        let des = Deserialiser::from(DesOptions::new().max_name_len(10));
+        assert_eq!(
+            des.read_source_add(buf.as_slice()).expect("failed des"),
+            rec
+        );
+
+        let des = Deserialiser::from(DesOptions::new().max_name_len(9));
+        assert!(matches!(
+            des.read_source_add(buf.as_slice()),
+            Err(IoError::Parser(DesError::NameLength(10)))
+        ));
+
+        // The limit splits the final character
+        let mut buf = Vec::new();
+        let rec = SourceAdd::new(32, 1, "pxls.spacé");
+        Serialiser
+            .write_source_add(&rec, &mut buf)
+            .expect("failed ser");
+        let des = Deserialiser::from(DesOptions::new().max_name_len(10));
+        assert!(matches!(
+            des.read_source_add(buf.as_slice()),
+            Err(IoError::Parser(DesError::NameLength(11)))
+        ));
+    }
 }
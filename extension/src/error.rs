@@ -1,9 +1,12 @@
 use std::fmt::Display;
 
+use msrf::error::{ErrorKind, MsrfError};
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum DesError {
-    UnexpectedType(u16),
+    UnexpectedType(u64),
     UnexpectedLength(u64),
+    NameLength(usize),
 }
 
 impl Display for DesError {
@@ -11,8 +14,19 @@ impl Display for DesError {
         match self {
             Self::UnexpectedType(id) => write!(f, "unexpected type id ({id:#06x})"),
             Self::UnexpectedLength(len) => write!(f, "record too small ({len})"),
+            Self::NameLength(len) => write!(f, "source name exceeds limit ({len})"),
         }
     }
 }
 
 impl std::error::Error for DesError {}
+
+impl MsrfError for DesError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::UnexpectedType(_) => ErrorKind::UnexpectedType,
+            Self::UnexpectedLength(_) => ErrorKind::Length,
+            Self::NameLength(_) => ErrorKind::Limit,
+        }
+    }
+}
@@ -1,21 +1,31 @@
 use std::{
     collections::{BTreeMap, btree_map::Entry},
+    io::{ErrorKind, Read},
     num::NonZeroU16,
 };
 
-use msrf::ConstAssignedId;
+use msrf::{
+    ConstAssignedId,
+    error::IoError,
+    io::{ReadExt, WriteExt},
+};
+
+use crate::error::DesError;
 
 pub mod codec;
 pub mod error;
+#[cfg(feature = "msrf-reader")]
 pub mod reader;
+#[cfg(feature = "msrf-writer")]
+pub mod rotate;
 pub mod writer;
 
 pub const MSRF_EXT_NAME: &str = "msrf-ext";
-pub const MSRF_EXT_MAP_ID: u16 = 0x00;
+pub const MSRF_EXT_MAP_ID: u64 = 0x00;
 pub const MSRF_EXT_VERSION: u16 = 0x00;
 
-pub const ID_SOURCE_ADD: u16 = 0x00;
-pub const ID_SOURCE_REMOVE: u16 = 0x01;
+pub const ID_SOURCE_ADD: u64 = 0x00;
+pub const ID_SOURCE_REMOVE: u64 = 0x01;
 
 // TODO: &str
 #[derive(Debug, Clone, PartialEq)]
@@ -36,7 +46,7 @@ impl SourceAdd {
 }
 
 impl ConstAssignedId for SourceAdd {
-    const TYPE_ID: u16 = ID_SOURCE_ADD;
+    const TYPE_ID: u64 = ID_SOURCE_ADD;
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -51,19 +61,19 @@ impl SourceRemove {
 }
 
 impl ConstAssignedId for SourceRemove {
-    const TYPE_ID: u16 = ID_SOURCE_REMOVE;
+    const TYPE_ID: u64 = ID_SOURCE_REMOVE;
 }
 
 #[derive(Debug, Clone, PartialEq)]
 #[non_exhaustive]
-#[repr(u16)]
+#[repr(u64)]
 pub enum Record {
     SourceAdd(SourceAdd) = SourceAdd::TYPE_ID,
     SourceRemove(SourceRemove) = SourceRemove::TYPE_ID,
 }
 
 impl Record {
-    pub fn type_id(&self) -> u16 {
+    pub fn type_id(&self) -> u64 {
         match self {
             Record::SourceAdd(_) => SourceAdd::TYPE_ID,
             Record::SourceRemove(_) => SourceRemove::TYPE_ID,
@@ -71,7 +81,7 @@ impl Record {
     }
 }
 
-impl From<Record> for u16 {
+impl From<Record> for u64 {
     fn from(value: Record) -> Self {
         value.type_id()
     }
@@ -148,7 +158,7 @@ impl SourceRegistrar {
         }
     }
 
-    pub fn register_existing<'a>(
+    pub fn register_existing(
         &mut self,
         id: NonZeroU16,
         name: impl Into<String>,
@@ -204,6 +214,49 @@ impl SourceRegistrar {
             .map(|(id, src)| (*id, src.name(), src.version()))
     }
 
+    /// Encodes all registered sources, e.g. to be stored alongside a reader checkpoint.
+    pub fn to_state(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        // SAFETY: Writing to a Vec<u8> cannot fail
+        buf.write_varint(self.map.len() as u64).unwrap();
+        for (id, src) in &self.map {
+            buf.write_u16(*id).unwrap();
+            buf.write_u16(src.version).unwrap();
+            buf.write_varint(src.name.len() as u64).unwrap();
+            buf.extend_from_slice(src.name.as_bytes());
+        }
+        buf
+    }
+
+    /// Restores a registrar previously encoded by [`SourceRegistrar::to_state`].
+    pub fn from_state(mut state: &[u8]) -> Result<SourceRegistrar, IoError<DesError>> {
+        let mut registrar = SourceRegistrar::new();
+        for _ in 0..state.read_varint()? {
+            let id = state.read_u16()?;
+            let version = state.read_u16()?;
+            let name_len = state.read_varint()?;
+            if name_len > state.len() as u64 {
+                return Err(IoError::Parser(DesError::UnexpectedLength(name_len)));
+            }
+
+            let mut name = String::new();
+            (&mut state).take(name_len).read_to_string(&mut name)?;
+
+            let existing = match NonZeroU16::new(id) {
+                Some(id) => registrar.register_existing(id, name, version),
+                None => registrar.register_root(name, version),
+            };
+            if existing.is_some() {
+                return Err(IoError::Io(std::io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("duplicate source id ({id})"),
+                )));
+            }
+        }
+
+        Ok(registrar)
+    }
+
     fn next_free_id(&self) -> NonZeroU16 {
         let mut id_candidate = self.next_id.get();
         for id in self
@@ -233,8 +286,6 @@ impl Default for SourceRegistrar {
 
 #[cfg(test)]
 mod test {
-    use std::u16;
-
     use super::*;
 
     const ROOT_A: &str = "msrf-ext";
@@ -332,4 +383,24 @@ mod test {
         assert_eq!(registrar.remove_by_id(1), Some(Source::new(ROOT_A, 567)));
         assert_eq!(registrar.next_free_id().get(), 1);
     }
+
+    #[test]
+    fn source_registrar_state() {
+        let mut registrar = SourceRegistrar::new();
+        assert_eq!(registrar.register_root(ROOT_A, 567), None);
+        assert_eq!(registrar.register(SOURCE_A, 123), Ok(1));
+        assert_eq!(registrar.register(SOURCE_B, 324), Ok(2));
+        assert_eq!(registrar.remove_by_id(1), Some(Source::new(SOURCE_A, 123)));
+
+        let mut restored = SourceRegistrar::from_state(&registrar.to_state()).unwrap();
+        assert!(registrar.sources().eq(restored.sources()));
+        assert_eq!(restored.register(SOURCE_C, 0), Ok(1));
+        assert_eq!(restored.register(SOURCE_A, 0), Ok(3));
+
+        let state = registrar.to_state();
+        assert!(matches!(
+            SourceRegistrar::from_state(&state[..state.len() - 1]),
+            Err(IoError::Parser(DesError::UnexpectedLength(_)))
+        ));
+    }
 }
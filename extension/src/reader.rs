@@ -13,7 +13,7 @@ pub struct MsrfExtReader<D> {
 impl<D: RawDeserialiser> MsrfExtReader<D> {
     pub fn read_record<R: Read>(
         &self,
-        id: u16,
+        id: u64,
         rdr: &mut RecordChunk<R>,
     ) -> Result<Record, IoError<DesError>> {
         match id {
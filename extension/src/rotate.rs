@@ -0,0 +1,142 @@
+use std::io::Write;
+
+use msrf::{
+    ConstAssignedId, RecordId,
+    codec::{IntoData, RawSerialiser as MsrfRawSerialiser},
+    error::{IoError, ParserError},
+    io::SizedValue,
+    rotate::SegmentHook,
+    writer::{HeaderInit, MsrfWriter},
+};
+
+use crate::{
+    MSRF_EXT_MAP_ID, SourceAdd, SourceRegistrar, codec::RawSerialiser, writer::MsrfExtWriter,
+};
+
+/// Replays the current [`SourceRegistrar`] as [`SourceAdd`] records at the start of every
+/// segment written by a [`msrf::rotate::RotatingMsrfWriter`].
+pub struct SourceReplay<S> {
+    registrar: SourceRegistrar,
+    ext: MsrfExtWriter<S>,
+}
+
+impl<S: RawSerialiser> SourceReplay<S> {
+    pub fn new(registrar: SourceRegistrar, ext: MsrfExtWriter<S>) -> SourceReplay<S> {
+        SourceReplay { registrar, ext }
+    }
+
+    pub fn registrar(&self) -> &SourceRegistrar {
+        &self.registrar
+    }
+
+    pub fn registrar_mut(&mut self) -> &mut SourceRegistrar {
+        &mut self.registrar
+    }
+
+    pub fn into_inner(self) -> (SourceRegistrar, MsrfExtWriter<S>) {
+        (self.registrar, self.ext)
+    }
+}
+
+impl<S, MS, W> SegmentHook<MS, W> for SourceReplay<S>
+where
+    S: RawSerialiser,
+    MS: MsrfRawSerialiser,
+    W: Write,
+{
+    fn segment_start(
+        &mut self,
+        wtr: &mut MsrfWriter<MS, W, HeaderInit>,
+    ) -> Result<(), IoError<ParserError>> {
+        let id = RecordId::new(MSRF_EXT_MAP_ID, SourceAdd::TYPE_ID);
+        for (source_id, name, version) in self.registrar.sources() {
+            let mut buf = Vec::new();
+            self.ext
+                .write_source_add(&mut buf, &SourceAdd::new(source_id, version, name))
+                .map_err(|e| match e {
+                    IoError::Io(e) => IoError::Io(e),
+                    IoError::Parser(e) => IoError::Io(std::io::Error::other(e)),
+                })?;
+            wtr.write_record_with(Encoded(buf), id)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct Encoded(Vec<u8>);
+
+impl<S> SizedValue<S> for Encoded {
+    fn encoded_len(&self, _ser: &S) -> usize {
+        self.0.len()
+    }
+}
+
+impl<S: MsrfRawSerialiser, W: Write> IntoData<S, W> for Encoded {
+    fn encode_into(
+        &self,
+        wtr: &mut W,
+        _ser: &S,
+        _source_id: u64,
+    ) -> Result<(), IoError<ParserError>> {
+        wtr.write_all(&self.0)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use msrf::{
+        reader::MsrfReader,
+        rotate::{RotatingMsrfWriter, RotationLimits},
+        writer::MsrfWriterBuilder,
+    };
+
+    use super::*;
+    use crate::{
+        codec::{AnyDeserialiser, RawDeserialiser, Version},
+        writer::MsrfExtWriterBuilder,
+    };
+
+    const SOURCE_A: &str = "pxls-space-ext";
+
+    #[test]
+    fn replay_sources() {
+        let mut registrar = SourceRegistrar::new();
+        let source_id = registrar.register(SOURCE_A, 3).unwrap();
+        let hook = SourceReplay::new(registrar, MsrfExtWriterBuilder::default().build());
+
+        let mut segments = Vec::new();
+        let limits = RotationLimits::new().max_records(1);
+        let mut wtr = RotatingMsrfWriter::with_hook(
+            MsrfWriterBuilder::new(),
+            limits,
+            |_| Ok(Vec::new()),
+            hook,
+        )
+        .unwrap();
+
+        for _ in 0..2 {
+            wtr.write_record_with(Encoded(vec![1, 2, 3]), RecordId::new(source_id.into(), 0))
+                .unwrap();
+            segments.push(wtr.current().get_ref().get_ref().clone());
+        }
+        assert_eq!(wtr.segment(), 1);
+
+        let des = AnyDeserialiser::new(Version::current());
+        for segment in segments {
+            let mut rdr = MsrfReader::new_unknown(Cursor::new(segment))
+                .initialise()
+                .unwrap();
+            let (id, mut chunk) = rdr.read_record().unwrap();
+            assert_eq!(id, RecordId::new(MSRF_EXT_MAP_ID, SourceAdd::TYPE_ID));
+            assert_eq!(
+                des.read_source_add(&mut chunk).unwrap(),
+                SourceAdd::new(source_id, 3, SOURCE_A)
+            );
+        }
+    }
+}
@@ -83,7 +83,7 @@ where
     SourceRemove: IntoMetadata<S>,
 {
     // TODO: Requires .clone() to use in practice, remove
-    pub fn generate_meta(&self, source_id: u16, record: impl Into<Record>) -> RecordMeta {
+    pub fn generate_meta(&self, source_id: u64, record: impl Into<Record>) -> RecordMeta {
         match record.into() {
             Record::SourceAdd(source_add) => source_add.meta(&self.ser, source_id),
             Record::SourceRemove(source_remove) => source_remove.meta(&self.ser, source_id),
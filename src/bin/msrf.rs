@@ -0,0 +1,72 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    process::ExitCode,
+};
+
+use msrf::{
+    CURRENT_VERSION,
+    convert::convert,
+    error::{IoError, ParserError},
+};
+
+const USAGE: &str = "usage: msrf convert <VERSION> <INPUT> <OUTPUT>";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        ["convert", version, input, output] => {
+            let Ok(version) = version.parse() else {
+                eprintln!("invalid version: {version} (latest is {CURRENT_VERSION})");
+                return ExitCode::from(2);
+            };
+            run_convert(version, input, output)
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn run_convert(version: u16, input: &str, output: &str) -> ExitCode {
+    let rdr = match File::open(input) {
+        Ok(file) => BufReader::new(file),
+        Err(e) => {
+            eprintln!("failed to open {input}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let wtr = match File::create(output) {
+        Ok(file) => BufWriter::new(file),
+        Err(e) => {
+            eprintln!("failed to create {output}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match convert(rdr, wtr, version) {
+        Ok(records) => {
+            eprintln!("converted {records} records to v{version}");
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprint!("{e}");
+            let mut source = std::error::Error::source(&e);
+            while let Some(e) = source {
+                // Displayed as its source, which follows
+                if !e.is::<IoError<ParserError>>() {
+                    eprint!(": {e}");
+                }
+                source = e.source();
+            }
+            eprintln!();
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use std::io::{Read, Write};
+
+use crate::{
+    RecordId,
+    error::{IoError, ParserError},
+    io::{ReadExt, WriteExt},
+};
+
+const CHECKPOINT_MAGIC: [u8; 4] = *b"MSRC";
+
+/// Snapshot of [`crate::reader::MsrfReader`] state between records, allowing a reader to be
+/// resumed on a seekable input via [`crate::reader::MsrfReader::resume`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub(crate) offset: u64,
+    pub(crate) header_offset: u64,
+    pub(crate) records: u64,
+    pub(crate) version: u16,
+    pub(crate) member: usize,
+    pub(crate) depth: Vec<(u64, RecordId)>,
+    pub(crate) state: Vec<u8>,
+}
+
+impl Checkpoint {
+    /// Offset of the next unread record from the start of the input.
+    #[must_use]
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Offset of the header of the stream containing [`Checkpoint::offset`].
+    #[must_use]
+    pub fn header_offset(&self) -> u64 {
+        self.header_offset
+    }
+
+    /// Number of records read before the checkpoint, excluding EoS.
+    #[must_use]
+    pub fn records(&self) -> u64 {
+        self.records
+    }
+
+    #[must_use]
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    #[must_use]
+    pub fn member(&self) -> usize {
+        self.member
+    }
+
+    // Top down
+    pub fn parents(&self) -> impl DoubleEndedIterator<Item = RecordId> {
+        self.depth.iter().rev().map(|(_, id)| id).copied()
+    }
+
+    /// Attaches opaque state (e.g. an encoded `SourceRegistrar`) to be restored alongside the
+    /// reader.
+    #[must_use]
+    pub fn with_state(mut self, state: Vec<u8>) -> Self {
+        self.state = state;
+        self
+    }
+
+    #[must_use]
+    pub fn state(&self) -> &[u8] {
+        &self.state
+    }
+
+    pub fn write_into<W: Write>(&self, mut wtr: W) -> Result<(), IoError<ParserError>> {
+        wtr.write_all(&CHECKPOINT_MAGIC)?;
+        wtr.write_varint(self.offset)?;
+        wtr.write_varint(self.header_offset)?;
+        wtr.write_varint(self.records)?;
+        wtr.write_u16(self.version)?;
+        wtr.write_varint(self.member as u64)?;
+        wtr.write_varint(self.depth.len() as u64)?;
+        for (count, id) in &self.depth {
+            wtr.write_varint(*count)?;
+            wtr.write_varint(id.source_id)?;
+            wtr.write_varint(id.type_id)?;
+        }
+        wtr.write_varint(self.state.len() as u64)?;
+        wtr.write_all(&self.state)?;
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(mut rdr: R) -> Result<Checkpoint, IoError<ParserError>> {
+        let magic_bytes = rdr.read_chunk()?;
+        if magic_bytes != CHECKPOINT_MAGIC {
+            return Err(IoError::Parser(ParserError::MagicBytes(magic_bytes)));
+        }
+
+        let offset = rdr.read_varint()?;
+        let header_offset = rdr.read_varint()?;
+        let records = rdr.read_varint()?;
+        let version = rdr.read_u16()?;
+        let member = rdr.read_varint()? as usize;
+
+        let depth_len = rdr.read_varint()?;
+        let mut depth = Vec::new();
+        for _ in 0..depth_len {
+            let count = rdr.read_varint()?;
+            let id = RecordId::new(rdr.read_varint()?, rdr.read_varint()?);
+            depth.push((count, id));
+        }
+
+        let state_len = rdr.read_varint()?;
+        let mut state = Vec::new();
+        let read = rdr.take(state_len).read_to_end(&mut state)?;
+        if read as u64 != state_len {
+            return Err(IoError::Parser(ParserError::Length(state_len)));
+        }
+
+        Ok(Checkpoint {
+            offset,
+            header_offset,
+            records,
+            version,
+            member,
+            depth,
+            state,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn serdes_checkpoint() {
+        let checkpoint = Checkpoint {
+            offset: 1234,
+            header_offset: 56,
+            records: 78,
+            version: 0,
+            member: 2,
+            depth: vec![(3, RecordId::new(1, 2)), (1, RecordId::new(3, 4))],
+            state: b"state".to_vec(),
+        };
+
+        let mut buf = Vec::new();
+        checkpoint.write_into(&mut buf).expect("ser fail");
+        let mut rdr = Cursor::new(buf);
+        assert_eq!(
+            Checkpoint::read_from(&mut rdr).expect("des fail"),
+            checkpoint
+        );
+        assert_eq!(rdr.position(), rdr.get_ref().len() as u64);
+    }
+
+    #[test]
+    fn des_checkpoint_truncated_state() {
+        let checkpoint = Checkpoint {
+            offset: 7,
+            header_offset: 0,
+            records: 0,
+            version: 0,
+            member: 0,
+            depth: Vec::new(),
+            state: b"state".to_vec(),
+        };
+
+        let mut buf = Vec::new();
+        checkpoint.write_into(&mut buf).expect("ser fail");
+        buf.pop();
+        assert!(matches!(
+            Checkpoint::read_from(Cursor::new(buf)),
+            Err(IoError::Parser(ParserError::Length(5)))
+        ));
+    }
+}
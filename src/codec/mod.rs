@@ -1,13 +1,15 @@
 pub mod v0;
+pub mod v1;
 
 use std::fmt::Debug;
 use std::io::{Read, Write};
+use std::sync::Arc;
 
 use crate::{
-    CURRENT_VERSION, Header, RecordMeta,
+    CURRENT_VERSION, EXTENDED_HEADER_VERSION, Features, Header, RecordMeta,
     codec::constants::{HEADER_LEN, MAGIC_BYTES},
-    error::{IoError, ParserError},
-    io::SizedValue,
+    error::{IoError, ParserError, ReadError},
+    io::{CountingReader, PVarint, ReadExt, SizedValue, WriteExt},
 };
 
 pub(crate) mod constants {
@@ -15,9 +17,204 @@ pub(crate) mod constants {
     pub const HEADER_LEN: usize = 7;
 }
 
-// TODO: Add options
-#[derive(Clone, Debug, Default, PartialEq, Eq)]
-pub struct DesOptions;
+/// Selects how [`crate::reader::MsrfReader`] treats input that is decodable, but could not have
+/// been produced by a conforming writer (see [`crate::error::ErrorKind::Malformed`] and
+/// [`ParserError::Guard`]).
+///
+/// Both modes read past EoS to detect trailing data (except in concatenated or follow mode),
+/// so should not be used if the input is expected to block after EoS.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strictness {
+    /// Reject with an error.
+    Strict,
+    /// Continue reading, reporting each occurrence to [`DesOptions::on_warning`] (if any).
+    Lenient,
+}
+
+pub type WarningCallback = Arc<dyn Fn(&ReadError) + Send + Sync>;
+
+/// Options applied while reading: limits guarding against untrusted input forcing unbounded
+/// allocation or recursion (all disabled by default), and the [`Strictness`] of validation.
+#[derive(Clone, Default)]
+pub struct DesOptions {
+    pub(crate) max_record_len: Option<u64>,
+    pub(crate) max_depth: Option<usize>,
+    pub(crate) max_children: Option<u64>,
+    pub(crate) max_records: Option<u64>,
+    pub(crate) max_header_len: Option<u64>,
+    pub(crate) strictness: Option<Strictness>,
+    pub(crate) on_warning: Option<WarningCallback>,
+    pub(crate) features: Features,
+    #[cfg(feature = "signatures")]
+    pub(crate) verifying_key: Option<crate::integrity::VerifyingKey>,
+    #[cfg(feature = "encryption")]
+    pub(crate) key_provider: Option<crate::encryption::KeyProvider>,
+}
+
+impl DesOptions {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Payload bytes per record, see [`ParserError::RecordLength`].
+    #[must_use]
+    pub fn max_record_len(mut self, max_record_len: u64) -> Self {
+        self.max_record_len = Some(max_record_len);
+        self
+    }
+
+    /// Nesting of non-empty containers, see [`ParserError::Depth`].
+    #[must_use]
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Records directly contained by a single container, see [`ParserError::Children`].
+    #[must_use]
+    pub fn max_children(mut self, max_children: u64) -> Self {
+        self.max_children = Some(max_children);
+        self
+    }
+
+    /// Records read in total excluding EoS, see [`ParserError::Records`].
+    #[must_use]
+    pub fn max_records(mut self, max_records: u64) -> Self {
+        self.max_records = Some(max_records);
+        self
+    }
+
+    /// Bytes of fields in an extended header, see [`ParserError::HeaderLength`].
+    #[must_use]
+    pub fn max_header_len(mut self, max_header_len: u64) -> Self {
+        self.max_header_len = Some(max_header_len);
+        self
+    }
+
+    /// If unset, only a non-zero guard is rejected and nothing else is checked.
+    #[must_use]
+    pub fn strictness(mut self, strictness: Strictness) -> Self {
+        self.strictness = Some(strictness);
+        self
+    }
+
+    /// Invoked with each violation tolerated in [`Strictness::Lenient`] mode.
+    #[must_use]
+    pub fn on_warning(mut self, on_warning: impl Fn(&ReadError) + Send + Sync + 'static) -> Self {
+        self.on_warning = Some(Arc::new(on_warning));
+        self
+    }
+
+    /// Required header features handled by the caller, in addition to [`Features::SUPPORTED`].
+    #[must_use]
+    pub fn supported_features(mut self, features: Features) -> Self {
+        self.features = features;
+        self
+    }
+
+    /// Requires streams to declare [`Features::INTEGRITY`] and their final digest to be signed
+    /// by `verifying_key`, see [`ParserError::Signature`].
+    #[cfg(feature = "signatures")]
+    #[must_use]
+    pub fn verifying_key(mut self, verifying_key: crate::integrity::VerifyingKey) -> Self {
+        self.verifying_key = Some(verifying_key);
+        self
+    }
+
+    /// Decrypts the payloads of sources `key_provider` returns a key for, failing with
+    /// [`ParserError::Decrypt`] on any not encrypted with it (whether or not listed by
+    /// [`Header::FIELD_ENCRYPTED`]). Payloads of other sources are read as is.
+    #[cfg(feature = "encryption")]
+    #[must_use]
+    pub fn key_provider(
+        mut self,
+        key_provider: impl Fn(u64) -> Option<crate::encryption::Key> + Send + Sync + 'static,
+    ) -> Self {
+        self.key_provider = Some(Arc::new(key_provider));
+        self
+    }
+
+    pub(crate) fn check_meta(&self, meta: &RecordMeta) -> Result<(), IoError<ParserError>> {
+        if self.max_record_len.is_some_and(|max| meta.length > max) {
+            return Err(IoError::Parser(ParserError::RecordLength(meta.length)));
+        }
+        if let Some(count) = meta.contained
+            && self.max_children.is_some_and(|max| count > max)
+        {
+            return Err(IoError::Parser(ParserError::Children(count)));
+        }
+        Ok(())
+    }
+
+    // Fails in strict mode, otherwise reports `error` as a warning
+    #[cfg(feature = "reader")]
+    pub(crate) fn violation(&self, error: ReadError) -> Result<(), ReadError> {
+        match self.strictness {
+            Some(Strictness::Strict) => Err(error),
+            Some(Strictness::Lenient) => {
+                if let Some(on_warning) = &self.on_warning {
+                    on_warning(&error);
+                }
+                Ok(())
+            }
+            None if error.kind() == crate::error::ErrorKind::Guard => Err(error),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Debug for DesOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut f = f.debug_struct("DesOptions");
+        f.field("max_record_len", &self.max_record_len)
+            .field("max_depth", &self.max_depth)
+            .field("max_children", &self.max_children)
+            .field("max_records", &self.max_records)
+            .field("max_header_len", &self.max_header_len)
+            .field("strictness", &self.strictness)
+            .field("on_warning", &self.on_warning.as_ref().map(|_| ".."))
+            .field("features", &self.features);
+        #[cfg(feature = "signatures")]
+        f.field("verifying_key", &self.verifying_key);
+        #[cfg(feature = "encryption")]
+        f.field("key_provider", &self.key_provider.as_ref().map(|_| ".."));
+        f.finish()
+    }
+}
+
+// Callbacks are only equal if they are the same allocation
+impl PartialEq for DesOptions {
+    fn eq(&self, other: &Self) -> bool {
+        #[cfg(feature = "signatures")]
+        if self.verifying_key != other.verifying_key {
+            return false;
+        }
+        #[cfg(feature = "encryption")]
+        if !ptr_eq(&self.key_provider, &other.key_provider) {
+            return false;
+        }
+
+        self.max_record_len == other.max_record_len
+            && self.max_depth == other.max_depth
+            && self.max_children == other.max_children
+            && self.max_records == other.max_records
+            && self.max_header_len == other.max_header_len
+            && self.strictness == other.strictness
+            && self.features == other.features
+            && ptr_eq(&self.on_warning, &other.on_warning)
+    }
+}
+
+fn ptr_eq<T: ?Sized>(lhs: &Option<Arc<T>>, rhs: &Option<Arc<T>>) -> bool {
+    match (lhs, rhs) {
+        (Some(lhs), Some(rhs)) => Arc::ptr_eq(lhs, rhs),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+impl Eq for DesOptions {}
 
 // TODO: Add options
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
@@ -25,11 +222,13 @@ pub struct SerOptions;
 
 pub trait RawDeserialiser {
     fn read_meta(&self, rdr: impl Read) -> Result<RecordMeta, IoError<ParserError>>;
+    /// Length of the minimal encoding of `meta`, used to detect non-minimal encodings.
+    fn encoded_meta_len(&self, meta: &RecordMeta) -> usize;
 }
 
 pub trait RawSerialiser {
     fn write_meta(&self, meta: RecordMeta, wtr: impl Write) -> Result<(), IoError<ParserError>>;
-    fn encoded_meta_len(&self, user_len: usize) -> usize;
+    fn encoded_meta_len(&self, meta: &RecordMeta) -> usize;
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -38,6 +237,9 @@ pub struct UnknownSerdes;
 #[derive(Debug, PartialEq, Eq)]
 pub enum AnyDeserialiser {
     V0(v0::Deserialiser),
+    V1(v1::Deserialiser),
+    /// Records are encoded as in v1, only the header differs.
+    V2(v1::Deserialiser),
 }
 
 impl AnyDeserialiser {
@@ -55,22 +257,41 @@ impl AnyDeserialiser {
         if version > CURRENT_VERSION {
             None
         } else {
-            Some(Self::new_impl(version, DesOptions))
+            Some(Self::new_impl(version, DesOptions::default()))
         }
     }
 
     fn new_impl(version: u16, options: DesOptions) -> Self {
         match version {
             0 => Self::V0(options.into()),
+            1 => Self::V1(options.into()),
+            2 => Self::V2(options.into()),
             _ => unreachable!(),
         }
     }
+
+    #[must_use]
+    pub fn version(&self) -> u16 {
+        match self {
+            AnyDeserialiser::V0(_) => 0,
+            AnyDeserialiser::V1(_) => 1,
+            AnyDeserialiser::V2(_) => 2,
+        }
+    }
 }
 
 impl RawDeserialiser for AnyDeserialiser {
     fn read_meta(&self, rdr: impl Read) -> Result<RecordMeta, IoError<ParserError>> {
         match self {
             AnyDeserialiser::V0(des) => des.read_meta(rdr),
+            AnyDeserialiser::V1(des) | AnyDeserialiser::V2(des) => des.read_meta(rdr),
+        }
+    }
+
+    fn encoded_meta_len(&self, meta: &RecordMeta) -> usize {
+        match self {
+            AnyDeserialiser::V0(des) => des.encoded_meta_len(meta),
+            AnyDeserialiser::V1(des) | AnyDeserialiser::V2(des) => des.encoded_meta_len(meta),
         }
     }
 }
@@ -78,6 +299,9 @@ impl RawDeserialiser for AnyDeserialiser {
 #[derive(Debug, PartialEq, Eq)]
 pub enum AnySerialiser {
     V0(v0::Serialiser),
+    V1(v1::Serialiser),
+    /// Records are encoded as in v1, only the header differs.
+    V2(v1::Serialiser),
 }
 
 impl AnySerialiser {
@@ -102,7 +326,18 @@ impl AnySerialiser {
     fn new_impl(version: u16, options: SerOptions) -> Self {
         match version {
             0 => Self::V0(options.into()),
-            _ => todo!("version control"),
+            1 => Self::V1(options.into()),
+            2 => Self::V2(options.into()),
+            _ => unreachable!(),
+        }
+    }
+
+    #[must_use]
+    pub fn version(&self) -> u16 {
+        match self {
+            AnySerialiser::V0(_) => 0,
+            AnySerialiser::V1(_) => 1,
+            AnySerialiser::V2(_) => 2,
         }
     }
 }
@@ -111,12 +346,14 @@ impl RawSerialiser for AnySerialiser {
     fn write_meta(&self, meta: RecordMeta, wtr: impl Write) -> Result<(), IoError<ParserError>> {
         match self {
             AnySerialiser::V0(ser) => ser.write_meta(meta, wtr),
+            AnySerialiser::V1(ser) | AnySerialiser::V2(ser) => ser.write_meta(meta, wtr),
         }
     }
 
-    fn encoded_meta_len(&self, user_len: usize) -> usize {
+    fn encoded_meta_len(&self, meta: &RecordMeta) -> usize {
         match self {
-            AnySerialiser::V0(ser) => ser.encoded_meta_len(user_len),
+            AnySerialiser::V0(ser) => ser.encoded_meta_len(meta),
+            AnySerialiser::V1(ser) | AnySerialiser::V2(ser) => ser.encoded_meta_len(meta),
         }
     }
 }
@@ -135,13 +372,92 @@ pub fn read_header(input: &[u8; HEADER_LEN]) -> Result<Header, ParserError> {
     // SAFETY: input[4..6].len() == 2
     let version = u16::from_le_bytes(input[4..6].try_into().unwrap());
 
-    Ok(Header { version })
+    Ok(Header::new(version))
 }
 
+/// Reads the remainder of a header following [`read_header`] (from
+/// [`EXTENDED_HEADER_VERSION`] onwards), returning the number of bytes read.
+///
+/// Fails if the header requires features outside of [`Features::SUPPORTED`] and those supported
+/// by `options`, or its fields exceed [`DesOptions::max_header_len`].
+pub fn read_header_extension(
+    rdr: impl Read,
+    header: &mut Header,
+    options: &DesOptions,
+) -> Result<u64, IoError<ParserError>> {
+    if header.version < EXTENDED_HEADER_VERSION {
+        return Ok(0);
+    }
+
+    let mut rdr = CountingReader::new(rdr);
+    header.required = Features::from_bits(rdr.read_varint()?);
+    header.optional = Features::from_bits(rdr.read_varint()?);
+
+    // Read incrementally, as the length is untrusted
+    let len = rdr.read_varint()?;
+    if options.max_header_len.is_some_and(|max| len > max) {
+        return Err(ParserError::HeaderLength(len).into());
+    }
+    let mut fields = Vec::new();
+    (&mut rdr).take(len).read_to_end(&mut fields)?;
+    if (fields.len() as u64) < len {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+    }
+
+    let mut area = fields.as_slice();
+    while !area.is_empty() {
+        let field = (|| {
+            let tag = area.read_varint().ok()?;
+            let field_len = usize::try_from(area.read_varint().ok()?).ok()?;
+            let value = area.get(..field_len)?.to_vec();
+            area = &area[field_len..];
+            Some((tag, value))
+        })();
+        header.fields.push(field.ok_or(ParserError::Length(len))?);
+    }
+
+    let [guard] = rdr.read_chunk()?;
+    if guard != 0 {
+        return Err(ParserError::Guard(guard).into());
+    }
+
+    let unsupported = header
+        .required
+        .difference(Features::SUPPORTED | options.features);
+    if !unsupported.is_empty() {
+        return Err(ParserError::Features(unsupported).into());
+    }
+    Ok(rdr.count())
+}
+
+/// Fails if `header` [`Header::is_extended`], but its version predates
+/// [`EXTENDED_HEADER_VERSION`].
 pub fn write_header<W: Write>(mut wtr: W, header: &Header) -> Result<(), IoError<ParserError>> {
+    let extended = header.version() >= EXTENDED_HEADER_VERSION;
+    if !extended && header.is_extended() {
+        return Err(ParserError::Unsupported(header.version()).into());
+    }
+
     wtr.write_all(&MAGIC_BYTES)?;
     wtr.write_all(&header.version().to_le_bytes())?;
     wtr.write_all(&[0x00])?;
+    if extended {
+        wtr.write_varint(header.required.bits())?;
+        wtr.write_varint(header.optional.bits())?;
+        let len: usize = header
+            .fields()
+            .map(|(tag, value)| {
+                PVarint::encode(tag).len() + PVarint::encode(value.len() as u64).len() + value.len()
+            })
+            .sum();
+        wtr.write_varint(len as u64)?;
+        for (tag, value) in header.fields() {
+            wtr.write_varint(tag)?;
+            wtr.write_varint(value.len() as u64)?;
+            wtr.write_all(value)?;
+        }
+        wtr.write_all(&[0x00])?;
+    }
     Ok(())
 }
 
@@ -150,7 +466,7 @@ where
     W: Write,
     S: RawSerialiser,
 {
-    fn encode_into(&self, wtr: &mut W, ser: &S, source_id: u16)
+    fn encode_into(&self, wtr: &mut W, ser: &S, source_id: u64)
     -> Result<(), IoError<ParserError>>;
 }
 
@@ -164,7 +480,7 @@ where
         &self,
         wtr: &mut W,
         ser: &S,
-        source_id: u16,
+        source_id: u64,
     ) -> Result<(), IoError<ParserError>> {
         for entry in *self {
             entry.encode_into(wtr, ser, source_id)?;
@@ -177,9 +493,11 @@ where
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::time::{Duration, SystemTime};
+
     use crate::{Header, codec::constants::MAGIC_BYTES};
 
-    pub(crate) const REF_HEADER: Header = Header { version: 3 };
+    pub(crate) const REF_HEADER: Header = Header::new(3);
 
     pub(crate) const REF_HEADER_BYTES: &[u8; HEADER_LEN] = constcat::concat_bytes!(
         &MAGIC_BYTES,  // Magic bytes
@@ -195,7 +513,7 @@ mod test {
 
     #[test]
     fn des_header_invalid_magic() {
-        let mut invalid_bytes = REF_HEADER_BYTES.clone();
+        let mut invalid_bytes = *REF_HEADER_BYTES;
         let invalid_magic = b"BAD!";
         invalid_bytes[..4].copy_from_slice(invalid_magic);
 
@@ -205,11 +523,123 @@ mod test {
 
     #[test]
     fn des_header_invalid_guard() {
-        let mut invalid_bytes = REF_HEADER_BYTES.clone();
+        let mut invalid_bytes = *REF_HEADER_BYTES;
         let invalid_guard = 42;
         invalid_bytes[6] = invalid_guard;
 
         let header = read_header(&invalid_bytes).expect_err("succeeded parse");
         assert_eq!(header, ParserError::Guard(invalid_guard));
     }
+
+    fn ref_header_extended() -> Header {
+        Header::new(EXTENDED_HEADER_VERSION)
+            .with_required(Features::from_bits(1 << 8))
+            .with_optional(Features::from_bits(1 << 2))
+            .with_producer("msrf")
+            .with_field(42, vec![])
+    }
+
+    const REF_HEADER_EXTENDED_BYTES: &[u8] = constcat::concat_bytes!(
+        &MAGIC_BYTES,       // Magic bytes
+        &[2_u8, 0_u8],      // Version
+        &[0x00],            // Guard
+        &[0b10, 0b100],     // Required: PV(256)
+        &[0b1001],          // Optional: PV(4)
+        &[0b1_0001],        // Fields length: PV(8)
+        &[0b111, 0b1001],   // Tag: PV(3), Length: PV(4)
+        b"msrf".as_slice(), // Producer
+        &[0b101_0101, 0b1], // Tag: PV(42), Length: PV(0)
+        &[0x00]             // Guard
+    );
+
+    #[test]
+    fn serdes_header_extended() {
+        let header = ref_header_extended();
+        let mut buf = Vec::new();
+        write_header(&mut buf, &header).expect("ser fail");
+        assert_eq!(buf, REF_HEADER_EXTENDED_BYTES);
+
+        let mut rdr = &buf[HEADER_LEN..];
+        let mut des = read_header(buf[..HEADER_LEN].try_into().unwrap()).expect("des fail");
+        let len = read_header_extension(
+            &mut rdr,
+            &mut des,
+            &DesOptions::new().supported_features(Features::from_bits(1 << 8)),
+        )
+        .expect("des fail");
+        assert_eq!(len as usize, buf.len() - HEADER_LEN);
+        assert!(rdr.is_empty());
+        assert_eq!(des, header);
+        assert_eq!(des.producer(), Some("msrf"));
+        assert_eq!(des.field(42), Some(&[][..]));
+        assert_eq!(des.uuid(), None);
+    }
+
+    #[test]
+    fn des_header_required_features() {
+        let mut header = Header::new(EXTENDED_HEADER_VERSION);
+        let err = read_header_extension(
+            &REF_HEADER_EXTENDED_BYTES[HEADER_LEN..],
+            &mut header,
+            &DesOptions::new(),
+        )
+        .expect_err("succeeded parse");
+        assert!(matches!(
+            err,
+            IoError::Parser(ParserError::Features(f)) if f == Features::from_bits(1 << 8)
+        ));
+    }
+
+    #[test]
+    fn des_header_truncated_field() {
+        let mut bytes = REF_HEADER_EXTENDED_BYTES[HEADER_LEN..].to_vec();
+        bytes[5] = 0b1_0101; // Length: PV(10), overruns fields
+        let err = read_header_extension(bytes.as_slice(), &mut Header::new(2), &DesOptions::new())
+            .expect_err("succeeded parse");
+        assert!(matches!(err, IoError::Parser(ParserError::Length(8))));
+    }
+
+    #[test]
+    fn des_header_length_limit() {
+        let bytes = &REF_HEADER_EXTENDED_BYTES[HEADER_LEN..];
+        let options = DesOptions::new().supported_features(Features::from_bits(1 << 8));
+        read_header_extension(
+            bytes,
+            &mut Header::new(2),
+            &options.clone().max_header_len(8),
+        )
+        .expect("des fail");
+        let err = read_header_extension(bytes, &mut Header::new(2), &options.max_header_len(7))
+            .expect_err("succeeded parse");
+        assert!(matches!(err, IoError::Parser(ParserError::HeaderLength(8))));
+    }
+
+    #[test]
+    fn ser_header_extended_unsupported() {
+        let header = ref_header_extended().with_optional(Features::NONE);
+        let header = Header {
+            version: 1,
+            ..header
+        };
+        assert!(matches!(
+            write_header(Vec::new(), &header),
+            Err(IoError::Parser(ParserError::Unsupported(1)))
+        ));
+    }
+
+    #[test]
+    fn header_fields() {
+        let created = SystemTime::UNIX_EPOCH + Duration::from_micros(1_700_000_000_000_000);
+        let header = Header::default()
+            .with_uuid([7; 16])
+            .with_created(created)
+            .with_producer("first")
+            .with_producer("second");
+        assert_eq!(header.uuid(), Some([7; 16]));
+        assert_eq!(header.created(), Some(created));
+        assert_eq!(header.producer(), Some("second"));
+        assert_eq!(header.fields().count(), 3);
+        assert!(header.is_extended());
+        assert!(!Header::default().is_extended());
+    }
 }
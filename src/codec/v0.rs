@@ -19,26 +19,46 @@ impl RawSerialiser for Serialiser {
         meta: RecordMeta,
         mut wtr: impl Write,
     ) -> Result<(), IoError<ParserError>> {
-        wtr.write_u16(meta.source_id)?;
-
-        if !meta.is_eos() {
-            if let Some(c) = meta.contained() {
-                wtr.write_u16(meta.type_id | TYPE_CONTAINER_MASK)?;
-                wtr.write_varint(meta.length)?;
-                wtr.write_u16(c)?;
-            } else {
-                wtr.write_u16(meta.type_id)?;
-                wtr.write_varint(meta.length)?;
-            }
+        if meta.is_eos() {
+            wtr.write_u16(RECORD_EOS)?;
+            return Ok(());
+        }
+
+        let source_id = narrow(meta.source_id, RECORD_EOS - 1)?;
+        let type_id = narrow(meta.type_id, !TYPE_CONTAINER_MASK)?;
+        wtr.write_u16(source_id)?;
+        if let Some(c) = meta.contained() {
+            wtr.write_u16(type_id | TYPE_CONTAINER_MASK)?;
+            wtr.write_varint(meta.length)?;
+            wtr.write_u16(narrow(c, u16::MAX)?)?;
+        } else {
+            wtr.write_u16(type_id)?;
+            wtr.write_varint(meta.length)?;
         }
 
         Ok(())
     }
 
-    fn encoded_meta_len(&self, user_len: usize) -> usize {
-        // TODO: Avoid full encode when len is needed?
-        let pv = PVarint::encode(user_len as u64);
-        pv.len() + ID_LEN
+    fn encoded_meta_len(&self, meta: &RecordMeta) -> usize {
+        encoded_meta_len(meta)
+    }
+}
+
+// Ids and counts are fixed width in v0
+fn narrow(val: u64, max: u16) -> Result<u16, IoError<ParserError>> {
+    u16::try_from(val)
+        .ok()
+        .filter(|val| *val <= max)
+        .ok_or(IoError::Parser(ParserError::Width(val)))
+}
+
+fn encoded_meta_len(meta: &RecordMeta) -> usize {
+    if meta.is_eos() {
+        size_of::<u16>()
+    } else if meta.is_container() {
+        ID_LEN + PVarint::encode(meta.length).len() + size_of::<u16>()
+    } else {
+        ID_LEN + PVarint::encode(meta.length).len()
     }
 }
 
@@ -60,19 +80,24 @@ impl RawDeserialiser for Deserialiser {
             return Ok(RecordMeta::new_eos());
         }
 
-        let mut type_id = rdr.read_u16()?;
+        let type_id = rdr.read_u16()?;
         let length = rdr.read_varint()?;
         let contained = (type_id & TYPE_CONTAINER_MASK > 0)
             .then(|| rdr.read_u16())
             .transpose()?;
 
-        type_id &= !TYPE_CONTAINER_MASK;
-        Ok(RecordMeta {
-            source_id,
-            type_id,
+        let meta = RecordMeta {
+            source_id: source_id.into(),
+            type_id: (type_id & !TYPE_CONTAINER_MASK).into(),
             length,
-            contained,
-        })
+            contained: contained.map(u64::from),
+        };
+        self.options.check_meta(&meta)?;
+        Ok(meta)
+    }
+
+    fn encoded_meta_len(&self, meta: &RecordMeta) -> usize {
+        encoded_meta_len(meta)
     }
 }
 
@@ -0,0 +1,174 @@
+use std::io::{Read, Write};
+
+use crate::codec::{DesOptions, RawDeserialiser, RawSerialiser, SerOptions};
+use crate::error::{IoError, ParserError};
+use crate::io::{PVarint, ReadExt, WriteExt};
+use crate::{RECORD_EOS, RecordMeta};
+
+pub const VERSION: usize = 1;
+// Type ids share a PVarint with the container flag (the least significant bit)
+pub const TYPE_ID_MAX: u64 = u64::MAX >> 1;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Serialiser {
+    options: SerOptions,
+}
+
+impl RawSerialiser for Serialiser {
+    fn write_meta(
+        &self,
+        meta: RecordMeta,
+        mut wtr: impl Write,
+    ) -> Result<(), IoError<ParserError>> {
+        wtr.write_varint(meta.source_id)?;
+
+        if !meta.is_eos() {
+            if meta.type_id > TYPE_ID_MAX {
+                return Err(IoError::Parser(ParserError::Width(meta.type_id)));
+            }
+
+            let flag = u64::from(meta.is_container());
+            wtr.write_varint(meta.type_id << 1 | flag)?;
+            wtr.write_varint(meta.length)?;
+            if let Some(c) = meta.contained() {
+                wtr.write_varint(c)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn encoded_meta_len(&self, meta: &RecordMeta) -> usize {
+        encoded_meta_len(meta)
+    }
+}
+
+fn encoded_meta_len(meta: &RecordMeta) -> usize {
+    let source_len = PVarint::encode(meta.source_id).len();
+    if meta.is_eos() {
+        return source_len;
+    }
+
+    let type_len = PVarint::encode(meta.type_id << 1).len();
+    let contained_len = meta.contained.map_or(0, |c| PVarint::encode(c).len());
+    source_len + type_len + PVarint::encode(meta.length).len() + contained_len
+}
+
+impl From<SerOptions> for Serialiser {
+    fn from(options: SerOptions) -> Self {
+        Serialiser { options }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Deserialiser {
+    options: DesOptions,
+}
+
+impl RawDeserialiser for Deserialiser {
+    fn read_meta(&self, mut rdr: impl Read) -> Result<RecordMeta, IoError<ParserError>> {
+        let source_id = rdr.read_varint()?;
+        if source_id == u64::from(RECORD_EOS) {
+            return Ok(RecordMeta::new_eos());
+        }
+
+        let type_id = rdr.read_varint()?;
+        let length = rdr.read_varint()?;
+        let contained = (type_id & 1 > 0).then(|| rdr.read_varint()).transpose()?;
+
+        let meta = RecordMeta {
+            source_id,
+            type_id: type_id >> 1,
+            length,
+            contained,
+        };
+        self.options.check_meta(&meta)?;
+        Ok(meta)
+    }
+
+    fn encoded_meta_len(&self, meta: &RecordMeta) -> usize {
+        encoded_meta_len(meta)
+    }
+}
+
+impl From<DesOptions> for Deserialiser {
+    fn from(options: DesOptions) -> Self {
+        Deserialiser { options }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::io::Cursor;
+
+    use super::*;
+
+    pub(crate) const REF_RECORD_META: RecordMeta = RecordMeta::new(16, 32, 6);
+
+    pub(crate) const REF_RECORD_META_CONTAINER: RecordMeta =
+        RecordMeta::new_container(16, 32, 6, 5);
+
+    pub(crate) const REF_RECORD_META_BYTES: &[u8; 3] = &[
+        0b10_0001,   // Source ID: PV(16)
+        0b1000_0001, // Type ID: PV(32 << 1)
+        0b1101,      // Length: PV(6)
+    ];
+
+    pub(crate) const REF_RECORD_META_CONTAINER_BYTES: &[u8; 4] = &[
+        0b10_0001,   // Source ID: PV(16)
+        0b1000_0011, // Type ID: PV(32 << 1 | 1)
+        0b1101,      // Length: PV(6)
+        0b1011,      // Contained: PV(5)
+    ];
+
+    fn serdes(meta: RecordMeta, bytes: &[u8]) {
+        let des = Deserialiser::default();
+        let ser = Serialiser::default();
+
+        let mut buf = Vec::new();
+        ser.write_meta(meta, &mut buf).expect("ser fail");
+        assert_eq!(buf, bytes);
+        assert_eq!(ser.encoded_meta_len(&meta), bytes.len());
+
+        let mut rdr = Cursor::new(buf);
+        assert_eq!(des.read_meta(&mut rdr).expect("des fail"), meta);
+        assert_eq!(rdr.position(), bytes.len() as u64);
+    }
+
+    #[test]
+    fn serdes_record() {
+        serdes(REF_RECORD_META, REF_RECORD_META_BYTES);
+    }
+
+    #[test]
+    fn serdes_record_container() {
+        serdes(REF_RECORD_META_CONTAINER, REF_RECORD_META_CONTAINER_BYTES);
+    }
+
+    #[test]
+    fn serdes_record_eos() {
+        serdes(RecordMeta::new_eos(), PVarint::encode(0xFFFF).as_slice());
+    }
+
+    #[test]
+    fn serdes_record_wide() {
+        let meta = RecordMeta::new_container(0x1_0000, 0x8000, 6, 0x1_0000);
+        let bytes = [
+            &[0b100, 0, 0b1000][..], // Source ID: PV(2^16)
+            &[0b1100, 0, 0b1000],    // Type ID: PV(2^15 << 1 | 1)
+            &[0b1101],               // Length: PV(6)
+            &[0b100, 0, 0b1000],     // Contained: PV(2^16)
+        ]
+        .concat();
+        serdes(meta, &bytes);
+    }
+
+    #[test]
+    fn ser_record_type_too_wide() {
+        let meta = RecordMeta::new(1, TYPE_ID_MAX + 1, 0);
+        assert!(matches!(
+            Serialiser::default().write_meta(meta, Vec::new()),
+            Err(IoError::Parser(ParserError::Width(_)))
+        ));
+    }
+}
@@ -0,0 +1,176 @@
+use std::{
+    cell::RefCell,
+    fmt::Debug,
+    io::{Read, Write},
+};
+
+use crate::{
+    codec::{IntoData, RawSerialiser},
+    error::{ConvertError, ErrorKind, IoError, ParserError},
+    io::{RecordChunk, SizedValue},
+    reader::MsrfReaderBuilder,
+    writer::MsrfWriterBuilder,
+};
+
+/// Rewrites the stream read from `rdr` (of any supported version) to `wtr` as `version`,
+/// returning the number of records copied.
+///
+/// See [`convert_with`].
+pub fn convert<R: Read, W: Write>(rdr: R, wtr: W, version: u16) -> Result<u64, ConvertError> {
+    convert_with(MsrfReaderBuilder::new(), rdr, wtr, version)
+}
+
+/// Rewrites the stream read from `rdr` by a reader built from `builder`, to `wtr` as `version`.
+///
+/// Containers and payloads are preserved byte-for-byte, as are the features and fields of the
+/// header. Fails on the first record the target version cannot represent (e.g. a source id too
+/// wide for v0), or with [`crate::error::BuildError::Extended`] if the header cannot be
+/// represented.
+pub fn convert_with<R: Read, W: Write>(
+    builder: MsrfReaderBuilder,
+    rdr: R,
+    wtr: W,
+    version: u16,
+) -> Result<u64, ConvertError> {
+    let mut rdr = builder.build(rdr)?;
+    let mut header = rdr.header().cloned().unwrap_or_default();
+    header.version = version;
+    let mut wtr = MsrfWriterBuilder::new()
+        .header(header)
+        .build(wtr)?
+        .initialise()
+        .map_err(|e| ConvertError::Write(rdr.annotate(e)))?;
+
+    loop {
+        let meta = match rdr.next_meta() {
+            Ok(meta) => meta,
+            Err(e) if e.kind() == ErrorKind::IsEos && rdr.is_finished() => break,
+            Err(e) => return Err(e.into()),
+        };
+
+        let payload = Payload(RefCell::new(rdr.chunk(meta.len())));
+        let res = wtr.write_raw(&payload, meta);
+        drop(payload);
+        res.map_err(|e| ConvertError::Write(rdr.annotate(e)))?;
+    }
+
+    wtr.finish()
+        .map_err(|e| ConvertError::Write(rdr.annotate(e)))?;
+    Ok(rdr.records())
+}
+
+// Streams the payload of a record being read directly into the writer
+struct Payload<'a, R: Read>(RefCell<RecordChunk<'a, R>>);
+
+impl<R: Read> Debug for Payload<'_, R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Payload")
+            .field(&self.0.borrow().len())
+            .finish()
+    }
+}
+
+impl<S, R: Read> SizedValue<S> for &Payload<'_, R> {
+    fn encoded_len(&self, _ser: &S) -> usize {
+        self.0.borrow().len() as usize
+    }
+}
+
+impl<S: RawSerialiser, W: Write, R: Read> IntoData<S, W> for &Payload<'_, R> {
+    fn encode_into(
+        &self,
+        wtr: &mut W,
+        _ser: &S,
+        _source_id: u64,
+    ) -> Result<(), IoError<ParserError>> {
+        let mut chunk = self.0.borrow_mut();
+        let len = chunk.len();
+        if std::io::copy(&mut *chunk, wtr)? < len {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{
+        EXTENDED_HEADER_VERSION, Header, error::BuildError, reader::MsrfReaderBuilder,
+        rotate::test::Value,
+    };
+
+    fn write_stream(version: u16, source_id: u64) -> Vec<u8> {
+        let mut wtr = MsrfWriterBuilder::new()
+            .version(version)
+            .build(Vec::new())
+            .expect("unsupported version")
+            .initialise()
+            .expect("failed to initialise");
+        wtr.write_container(Value(0), source_id, 2)
+            .expect("failed write");
+        wtr.write_container(Value(1), source_id, 0)
+            .expect("failed write");
+        wtr.write_record(Value(2), source_id).expect("failed write");
+        wtr.write_record(Value(3), source_id).expect("failed write");
+        wtr.finish().expect("failed finish")
+    }
+
+    #[test]
+    fn convert_roundtrip() {
+        let v0 = write_stream(0, 1);
+        let mut v2 = Vec::new();
+        assert_eq!(
+            convert(Cursor::new(&v0), &mut v2, 2).expect("failed convert"),
+            4
+        );
+        assert_eq!(v2, write_stream(2, 1));
+
+        let mut out = Vec::new();
+        convert(Cursor::new(&v2), &mut out, 0).expect("failed convert");
+        assert_eq!(out, v0);
+    }
+
+    #[test]
+    fn convert_too_wide() {
+        let v1 = write_stream(1, 0x1_0000);
+        let err = convert(Cursor::new(&v1), Vec::new(), 0).expect_err("succeeded convert");
+        let ConvertError::Write(e) = err else {
+            panic!("unexpected error: {err:?}");
+        };
+        assert!(matches!(
+            e.error(),
+            IoError::Parser(ParserError::Width(0x1_0000))
+        ));
+        assert_eq!(e.position().record(), 0);
+    }
+
+    #[test]
+    fn convert_extended_header() {
+        let wtr = MsrfWriterBuilder::new()
+            .header(Header::new(EXTENDED_HEADER_VERSION).with_producer("test"))
+            .build(Vec::new())
+            .expect("unsupported version")
+            .initialise()
+            .expect("failed to initialise");
+        let data = wtr.finish().expect("failed finish");
+
+        let mut out = Vec::new();
+        convert(Cursor::new(&data), &mut out, 2).expect("failed convert");
+        assert_eq!(out, data);
+
+        assert!(matches!(
+            convert(Cursor::new(&data), Vec::new(), 1),
+            Err(ConvertError::Build(BuildError::Extended(1)))
+        ));
+        let rdr = MsrfReaderBuilder::new().build(Cursor::new(out));
+        assert_eq!(
+            rdr.expect("failed to build")
+                .header()
+                .and_then(Header::producer),
+            Some("test")
+        );
+    }
+}
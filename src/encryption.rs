@@ -0,0 +1,266 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+#[cfg(feature = "reader")]
+use chacha20poly1305::XNonce;
+#[cfg(feature = "writer")]
+use chacha20poly1305::aead::{AeadCore, OsRng};
+use chacha20poly1305::{
+    KeyInit, XChaCha20Poly1305,
+    aead::{Aead, Payload},
+};
+
+use crate::{RecordMeta, io::PVarint};
+#[cfg(feature = "reader")]
+use crate::{codec::DesOptions, error::ParserError};
+
+pub const KEY_LEN: usize = 32;
+pub const NONCE_LEN: usize = 24;
+pub const TAG_LEN: usize = 16;
+/// Bytes added to each encrypted payload, a random nonce preceding and a tag following it.
+pub const OVERHEAD: u64 = (NONCE_LEN + TAG_LEN) as u64;
+
+/// XChaCha20-Poly1305 key.
+pub type Key = [u8; KEY_LEN];
+
+/// Returns the key of a source id (if any), see [`crate::codec::DesOptions::key_provider`].
+pub type KeyProvider = Arc<dyn Fn(u64) -> Option<Key> + Send + Sync>;
+
+/// Keys of the sources whose payloads are encrypted, see
+/// [`crate::writer::MsrfWriterBuilder::encryption`].
+#[derive(Clone, Default)]
+pub struct EncryptionOptions {
+    pub(crate) keys: BTreeMap<u64, Key>,
+}
+
+impl EncryptionOptions {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn key(mut self, source_id: u64, key: Key) -> Self {
+        self.keys.insert(source_id, key);
+        self
+    }
+
+    #[cfg(feature = "writer")]
+    pub(crate) fn get(&self, source_id: u64) -> Option<&Key> {
+        self.keys.get(&source_id)
+    }
+
+    // Value of `Header::FIELD_ENCRYPTED`
+    #[cfg(feature = "writer")]
+    pub(crate) fn field(&self) -> Vec<u8> {
+        self.keys
+            .keys()
+            .flat_map(|id| PVarint::encode(*id).as_slice().to_vec())
+            .collect()
+    }
+}
+
+// Keys are omitted
+impl std::fmt::Debug for EncryptionOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EncryptionOptions")
+            .field("sources", &self.keys.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// Plaintext of the current record of the stream being read.
+#[cfg(feature = "reader")]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Decryption {
+    pub(crate) plaintext: Vec<u8>,
+}
+
+#[cfg(feature = "reader")]
+impl Decryption {
+    // Sources with a key must be encrypted whether or not the header lists them, so plaintext
+    // cannot be substituted by dropping a source from the list
+    pub(crate) fn key(&self, source_id: u64, options: &DesOptions) -> Option<Key> {
+        options.key_provider.as_ref()?(source_id)
+    }
+}
+
+// Independent of the format version, so encrypted records survive conversion. The index (records
+// preceding it, excluding digest records) stops records from being reordered or replayed
+fn associated_data(meta: &RecordMeta, index: u64) -> Vec<u8> {
+    let contained = meta.contained().map_or(0, |count| count + 1);
+    [
+        meta.source_id(),
+        meta.type_id(),
+        meta.len(),
+        contained,
+        index,
+    ]
+    .into_iter()
+    .flat_map(|value| PVarint::encode(value).as_slice().to_vec())
+    .collect()
+}
+
+/// Encrypts `plaintext` authenticating `meta` and the record's `index` within the stream, where
+/// the length of `meta` must include [`OVERHEAD`].
+#[cfg(feature = "writer")]
+pub(crate) fn encrypt(key: &Key, meta: &RecordMeta, index: u64, plaintext: &[u8]) -> Vec<u8> {
+    let cipher = XChaCha20Poly1305::new(key.into());
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let aad = associated_data(meta, index);
+    let payload = Payload {
+        msg: plaintext,
+        aad: &aad,
+    };
+    // Only fails if the plaintext exceeds the cipher's limit (256 GiB)
+    let ciphertext = cipher.encrypt(&nonce, payload).expect("plaintext too long");
+    [nonce.as_slice(), &ciphertext].concat()
+}
+
+#[cfg(feature = "reader")]
+pub(crate) fn decrypt(
+    key: &Key,
+    meta: &RecordMeta,
+    index: u64,
+    payload: &[u8],
+) -> Result<Vec<u8>, ParserError> {
+    let error = ParserError::Decrypt(meta.source_id());
+    if payload.len() < OVERHEAD as usize {
+        return Err(error);
+    }
+
+    let (nonce, ciphertext) = payload.split_at(NONCE_LEN);
+    let cipher = XChaCha20Poly1305::new(key.into());
+    let aad = associated_data(meta, index);
+    let payload = Payload {
+        msg: ciphertext,
+        aad: &aad,
+    };
+    cipher
+        .decrypt(XNonce::from_slice(nonce), payload)
+        .map_err(|_| error)
+}
+
+#[cfg(all(test, feature = "reader", feature = "writer"))]
+mod test {
+    use std::io::{Cursor, Read};
+
+    use super::*;
+    use crate::{
+        EXTENDED_HEADER_VERSION, Header,
+        codec::DesOptions,
+        error::{ErrorKind, IoError, ParserError, ReadError},
+        reader::MsrfReaderBuilder,
+        rotate::test::Value,
+        writer::MsrfWriterBuilder,
+    };
+
+    const KEY: Key = [7; KEY_LEN];
+
+    // Source 1 is encrypted, while source 2 is not
+    fn write_stream() -> Vec<u8> {
+        let mut wtr = MsrfWriterBuilder::new()
+            .version(EXTENDED_HEADER_VERSION)
+            .encryption(EncryptionOptions::new().key(1, KEY))
+            .build(Vec::new())
+            .expect("unsupported version")
+            .initialise()
+            .expect("failed to initialise");
+        wtr.write_record(Value(1), 1).expect("failed write");
+        wtr.write_record(Value(2), 2).expect("failed write");
+        wtr.finish().expect("failed finish")
+    }
+
+    fn read_stream(data: Vec<u8>, options: DesOptions) -> Result<Vec<Vec<u8>>, ReadError> {
+        let mut rdr = MsrfReaderBuilder::new()
+            .options(options)
+            .build(Cursor::new(data))
+            .expect("failed to build");
+        assert_eq!(
+            rdr.header().and_then(Header::encrypted_sources),
+            Some(vec![1])
+        );
+
+        let mut payloads = Vec::new();
+        loop {
+            match rdr.read_record() {
+                Ok((_, mut user_rdr)) => {
+                    let mut payload = Vec::new();
+                    user_rdr.read_to_end(&mut payload).expect("io fail");
+                    payloads.push(payload);
+                }
+                Err(e) if e.kind() == ErrorKind::IsEos => return Ok(payloads),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    #[test]
+    fn decrypt_records() {
+        let options = DesOptions::new().key_provider(|source_id| (source_id == 1).then_some(KEY));
+        let payloads = read_stream(write_stream(), options).expect("failed read");
+        assert_eq!(payloads, [[1], [2]]);
+
+        // Encrypted payloads are read as is without a key
+        let payloads = read_stream(write_stream(), DesOptions::new()).expect("failed read");
+        assert_eq!(payloads[0].len() as u64, 1 + OVERHEAD);
+        assert_eq!(payloads[1], [2]);
+    }
+
+    #[test]
+    fn decrypt_records_wrong_key() {
+        let options = DesOptions::new().key_provider(|_| Some([8; KEY_LEN]));
+        let err = read_stream(write_stream(), options).expect_err("succeeded read");
+        assert_eq!(err.kind(), ErrorKind::Integrity);
+        assert!(matches!(
+            err.error(),
+            IoError::Parser(ParserError::Decrypt(1))
+        ));
+        assert_eq!(err.position().record(), 0);
+    }
+
+    #[test]
+    fn decrypt_records_unlisted() {
+        // Source 2 is not listed, but its records must be encrypted once a key is provided
+        let options = DesOptions::new().key_provider(|_| Some(KEY));
+        let err = read_stream(write_stream(), options).expect_err("succeeded read");
+        assert!(matches!(
+            err.error(),
+            IoError::Parser(ParserError::Decrypt(2))
+        ));
+        assert_eq!(err.position().record(), 1);
+    }
+
+    #[test]
+    fn decrypt_records_reordered() {
+        let mut wtr = MsrfWriterBuilder::new()
+            .version(EXTENDED_HEADER_VERSION)
+            .encryption(EncryptionOptions::new().key(1, KEY))
+            .build(Vec::new())
+            .expect("unsupported version")
+            .initialise()
+            .expect("failed to initialise");
+        let mut spans = Vec::new();
+        for value in [1, 3] {
+            wtr.write_record(Value(value), 1).expect("failed write");
+            spans.extend(wtr.record_span());
+        }
+        let data = wtr.finish().expect("failed finish");
+
+        // Records of equal length from the same source, swapped
+        let (first, second) = (spans[0], spans[1]);
+        let mut swapped = data[..first.meta as usize].to_vec();
+        swapped.extend_from_slice(&data[second.meta as usize..second.end as usize + 1]);
+        swapped.extend_from_slice(&data[first.meta as usize..first.end as usize + 1]);
+        swapped.extend_from_slice(&data[second.end as usize + 1..]);
+        assert_eq!(swapped.len(), data.len());
+
+        let options = DesOptions::new().key_provider(|_| Some(KEY));
+        let err = read_stream(swapped, options).expect_err("succeeded read");
+        assert!(matches!(
+            err.error(),
+            IoError::Parser(ParserError::Decrypt(1))
+        ));
+        assert_eq!(err.position().record(), 0);
+    }
+}
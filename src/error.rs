@@ -1,31 +1,167 @@
 use std::{error::Error, fmt::Display};
 
+use crate::{Features, RecordId};
+
+/// Broad category of an error, shared by MSRF and its extensions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    Io,
+    Unsupported,
+    Guard,
+    MagicBytes,
+    Length,
+    UnexpectedEos,
+    IsEos,
+    Checkpoint,
+    UnexpectedType,
+    /// A limit set by [`crate::codec::DesOptions`] (or an extension's equivalent) was exceeded.
+    Limit,
+    /// Decodable, but not produced by a conforming writer. Only fatal in strict mode, see
+    /// [`crate::codec::Strictness`].
+    Malformed,
+    /// The stream does not match a digest or summary written alongside it.
+    Integrity,
+    /// A previous error left the reader or writer mid-record, see [`ParserError::Poisoned`].
+    Poisoned,
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io => write!(f, "io"),
+            Self::Unsupported => write!(f, "unsupported"),
+            Self::Guard => write!(f, "guard"),
+            Self::MagicBytes => write!(f, "magic bytes"),
+            Self::Length => write!(f, "length"),
+            Self::UnexpectedEos => write!(f, "unexpected eos"),
+            Self::IsEos => write!(f, "is eos"),
+            Self::Checkpoint => write!(f, "checkpoint"),
+            Self::UnexpectedType => write!(f, "unexpected type"),
+            Self::Limit => write!(f, "limit exceeded"),
+            Self::Malformed => write!(f, "malformed"),
+            Self::Integrity => write!(f, "integrity"),
+            Self::Poisoned => write!(f, "poisoned"),
+        }
+    }
+}
+
+/// Implemented by parser errors of MSRF and its extensions.
+pub trait MsrfError: Error {
+    fn kind(&self) -> ErrorKind;
+}
+
 // TODO: Re-evaluate variant nessicity (e.g. length?)
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub enum ParserError {
-    Need(usize), // TODO: Remove
     Unsupported(u16),
+    /// The header declares a version other than the one expected, see [`BuildError::Version`].
+    Version {
+        expected: u16,
+        found: u16,
+    },
+    /// The header is extended, but its version predates it, see [`BuildError::Extended`].
+    Extended(u16),
     Guard(u8),
     MagicBytes([u8; 4]),
     Length(u64),
     UnexpectedEos,
     IsEos,
+    Checkpoint(u64),
+    RecordLength(u64),
+    Depth(usize),
+    Children(u64),
+    Records(u64),
+    /// The fields of an extended header exceed [`crate::codec::DesOptions::max_header_len`].
+    HeaderLength(u64),
+    NonMinimal(u64),
+    EmptyContainer,
+    /// The id is reserved by a feature the stream declares, see
+    /// [`crate::integrity::DIGEST_RECORD`].
+    Reserved(RecordId),
+    TrailingData,
+    Width(u64),
+    /// The header requires features which are not supported.
+    Features(Features),
+    /// The trailer does not match the records read, see [`crate::trailer::Trailer`].
+    Trailer,
+    /// A digest record does not match the stream, records after the count are unverified.
+    Digest(u64),
+    /// The final digest is unsigned or its signature is invalid, see [`Self::Digest`].
+    Signature(u64),
+    /// The payload of an encrypted record from the source failed authentication.
+    Decrypt(u64),
+    /// A previous error left the reader at an unknown position within the stream, or the writer
+    /// mid-record.
+    Poisoned,
 }
 
 impl Error for ParserError {}
 
+impl MsrfError for ParserError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Unsupported(_)
+            | Self::Version { .. }
+            | Self::Extended(_)
+            | Self::Width(_)
+            | Self::Features(_)
+            | Self::Reserved(_) => ErrorKind::Unsupported,
+            Self::Guard(_) => ErrorKind::Guard,
+            Self::MagicBytes(_) => ErrorKind::MagicBytes,
+            Self::Length(_) => ErrorKind::Length,
+            Self::UnexpectedEos => ErrorKind::UnexpectedEos,
+            Self::IsEos => ErrorKind::IsEos,
+            Self::Checkpoint(_) => ErrorKind::Checkpoint,
+            Self::RecordLength(_)
+            | Self::Depth(_)
+            | Self::Children(_)
+            | Self::Records(_)
+            | Self::HeaderLength(_) => ErrorKind::Limit,
+            Self::NonMinimal(_) | Self::EmptyContainer | Self::TrailingData => ErrorKind::Malformed,
+            Self::Trailer | Self::Digest(_) | Self::Signature(_) | Self::Decrypt(_) => {
+                ErrorKind::Integrity
+            }
+            Self::Poisoned => ErrorKind::Poisoned,
+        }
+    }
+}
+
 impl Display for ParserError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::Need(n) => write!(f, "need {n} more bytes to continue"),
             Self::Unsupported(ver) => {
                 write!(f, "unsupported version (v{ver})")
             }
+            Self::Version { expected, found } => {
+                write!(
+                    f,
+                    "unexpected version (expected v{expected}, found v{found})"
+                )
+            }
+            Self::Extended(ver) => write!(f, "extended header unsupported by version (v{ver})"),
             Self::Guard(g) => write!(f, "expected guard ({g})"),
             Self::MagicBytes(b) => write!(f, "invalid magic bytes ({b:?})"),
             Self::Length(l) => write!(f, "invalid length ({l})"),
             Self::UnexpectedEos => write!(f, "unexpected eos"),
             Self::IsEos => write!(f, "already recieved eos"),
+            Self::Checkpoint(o) => write!(f, "checkpoint inconsistent with stream ({o})"),
+            Self::RecordLength(l) => write!(f, "record length exceeds limit ({l})"),
+            Self::Depth(d) => write!(f, "container depth exceeds limit ({d})"),
+            Self::Children(c) => write!(f, "container children exceed limit ({c})"),
+            Self::Records(r) => write!(f, "record count exceeds limit ({r})"),
+            Self::HeaderLength(l) => write!(f, "header length exceeds limit ({l})"),
+            Self::NonMinimal(l) => write!(f, "non-minimal length encoding ({l})"),
+            Self::EmptyContainer => write!(f, "container flagged without children"),
+            Self::Reserved(id) => write!(f, "reserved id ({id})"),
+            Self::TrailingData => write!(f, "trailing data after eos"),
+            Self::Width(v) => write!(f, "value too wide for format version ({v})"),
+            Self::Features(b) => write!(f, "unsupported required features ({:#x})", b.bits()),
+            Self::Trailer => write!(f, "trailer does not match stream"),
+            Self::Digest(n) => write!(f, "digest mismatch, records after {n} unverified"),
+            Self::Signature(n) => write!(f, "invalid signature, records after {n} unverified"),
+            Self::Decrypt(id) => write!(f, "failed to decrypt payload (source {id})"),
+            Self::Poisoned => write!(f, "poisoned by a previous error"),
         }
     }
 }
@@ -36,7 +172,30 @@ pub enum IoError<E> {
     Io(std::io::Error),
 }
 
-impl<E: Error> Error for IoError<E> {}
+impl<E: MsrfError> IoError<E> {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            IoError::Parser(e) => e.kind(),
+            IoError::Io(_) => ErrorKind::Io,
+        }
+    }
+}
+
+// Displayed as the inner error, which is also chained as its source
+impl<E: Error + 'static> Error for IoError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            IoError::Parser(e) => Some(e),
+            IoError::Io(e) => Some(e),
+        }
+    }
+}
+
+impl<E: MsrfError + 'static> MsrfError for IoError<E> {
+    fn kind(&self) -> ErrorKind {
+        IoError::kind(self)
+    }
+}
 
 impl<E: Error> Display for IoError<E> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -59,3 +218,388 @@ impl<E: Error> From<std::io::Error> for IoError<E> {
         Self::Io(value)
     }
 }
+
+/// Location within a stream at which an error occurred.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Position {
+    pub(crate) offset: u64,
+    pub(crate) record: u64,
+    pub(crate) id: Option<RecordId>,
+    pub(crate) path: Vec<RecordId>,
+}
+
+impl Position {
+    #[must_use]
+    pub fn new(offset: u64, record: u64) -> Self {
+        Self {
+            offset,
+            record,
+            id: None,
+            path: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_id(mut self, id: RecordId) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    #[must_use]
+    pub fn with_path(mut self, path: impl IntoIterator<Item = RecordId>) -> Self {
+        self.path = path.into_iter().collect();
+        self
+    }
+
+    /// Byte offset from the start of the input.
+    #[must_use]
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Index of the record being read, starting from 0.
+    #[must_use]
+    pub fn record(&self) -> u64 {
+        self.record
+    }
+
+    /// Id of the record being read, if its meta was decoded.
+    #[must_use]
+    pub fn id(&self) -> Option<RecordId> {
+        self.id
+    }
+
+    /// Enclosing containers, top down.
+    #[must_use]
+    pub fn path(&self) -> &[RecordId] {
+        &self.path
+    }
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "offset {} (record {}", self.offset, self.record)?;
+        if let Some(id) = self.id {
+            write!(f, ", id {id}")?;
+        }
+        if !self.path.is_empty() {
+            write!(f, ", path ")?;
+            for (i, id) in self.path.iter().enumerate() {
+                if i > 0 {
+                    write!(f, "/")?;
+                }
+                write!(f, "{id}")?;
+            }
+        }
+        write!(f, ")")
+    }
+}
+
+/// An [`IoError`] annotated with the [`Position`] at which it occurred.
+#[derive(Debug)]
+pub struct PositionedError<E> {
+    error: IoError<E>,
+    position: Box<Position>,
+}
+
+pub type ReadError = PositionedError<ParserError>;
+
+impl<E> PositionedError<E> {
+    pub fn new(error: impl Into<IoError<E>>, position: Position) -> Self {
+        Self {
+            error: error.into(),
+            position: Box::new(position),
+        }
+    }
+
+    pub fn error(&self) -> &IoError<E> {
+        &self.error
+    }
+
+    pub fn position(&self) -> &Position {
+        &self.position
+    }
+
+    pub fn into_inner(self) -> IoError<E> {
+        self.error
+    }
+}
+
+impl<E: MsrfError> PositionedError<E> {
+    pub fn kind(&self) -> ErrorKind {
+        self.error.kind()
+    }
+}
+
+impl<E: Error + 'static> Error for PositionedError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl<E: MsrfError + 'static> MsrfError for PositionedError<E> {
+    fn kind(&self) -> ErrorKind {
+        self.error.kind()
+    }
+}
+
+impl<E> Display for PositionedError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "failed at {}", self.position)
+    }
+}
+
+impl<E> From<PositionedError<E>> for IoError<E> {
+    fn from(value: PositionedError<E>) -> Self {
+        value.error
+    }
+}
+
+/// Returned by [`crate::writer::MsrfWriterBuilder`] and [`crate::reader::MsrfReaderBuilder`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum BuildError {
+    /// The requested version is newer than this library supports.
+    Unsupported(u16),
+    /// The header declares a version other than the one requested.
+    Version { expected: u16, found: u16 },
+    /// The header has features or fields, but the requested version predates
+    /// [`crate::EXTENDED_HEADER_VERSION`].
+    Extended(u16),
+    /// The header could not be read.
+    Read(ReadError),
+}
+
+impl BuildError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Unsupported(_) | Self::Version { .. } | Self::Extended(_) => {
+                ErrorKind::Unsupported
+            }
+            Self::Read(e) => e.kind(),
+        }
+    }
+}
+
+impl Error for BuildError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Read(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl MsrfError for BuildError {
+    fn kind(&self) -> ErrorKind {
+        BuildError::kind(self)
+    }
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unsupported(ver) => write!(f, "unsupported version (v{ver})"),
+            Self::Version { expected, found } => {
+                write!(
+                    f,
+                    "unexpected version (expected v{expected}, found v{found})"
+                )
+            }
+            Self::Extended(ver) => write!(f, "extended header unsupported by version (v{ver})"),
+            Self::Read(_) => write!(f, "failed to read header"),
+        }
+    }
+}
+
+impl From<ReadError> for BuildError {
+    fn from(value: ReadError) -> Self {
+        Self::Read(value)
+    }
+}
+
+impl From<BuildError> for IoError<ParserError> {
+    fn from(value: BuildError) -> Self {
+        match value {
+            BuildError::Unsupported(ver) => IoError::Parser(ParserError::Unsupported(ver)),
+            BuildError::Version { expected, found } => {
+                IoError::Parser(ParserError::Version { expected, found })
+            }
+            BuildError::Extended(ver) => IoError::Parser(ParserError::Extended(ver)),
+            BuildError::Read(e) => e.into_inner(),
+        }
+    }
+}
+
+/// Returned by [`crate::convert::convert`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ConvertError {
+    /// The input header could not be read, or cannot be written as the target version.
+    Build(BuildError),
+    /// The input could not be read.
+    Read(ReadError),
+    /// A record could not be written, positioned within the input.
+    Write(ReadError),
+}
+
+impl ConvertError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Build(e) => e.kind(),
+            Self::Read(e) | Self::Write(e) => e.kind(),
+        }
+    }
+}
+
+impl Error for ConvertError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Build(e) => Some(e),
+            Self::Read(e) | Self::Write(e) => Some(e),
+        }
+    }
+}
+
+impl MsrfError for ConvertError {
+    fn kind(&self) -> ErrorKind {
+        ConvertError::kind(self)
+    }
+}
+
+impl Display for ConvertError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Build(_) => write!(f, "failed to convert header"),
+            Self::Read(_) => write!(f, "failed to read input"),
+            Self::Write(_) => write!(f, "failed to write record read from input"),
+        }
+    }
+}
+
+impl From<BuildError> for ConvertError {
+    fn from(value: BuildError) -> Self {
+        Self::Build(value)
+    }
+}
+
+impl From<ReadError> for ConvertError {
+    fn from(value: ReadError) -> Self {
+        Self::Read(value)
+    }
+}
+
+/// Returned by [`crate::writer::MsrfWriter::write_records`] and
+/// [`crate::writer::MsrfWriter::write_container_from_iter`] once a record fails, after those
+/// preceding it were written.
+#[derive(Debug)]
+pub struct BatchError {
+    written: u64,
+    error: IoError<ParserError>,
+}
+
+impl BatchError {
+    #[cfg(feature = "writer")]
+    pub(crate) fn new(written: u64, error: IoError<ParserError>) -> Self {
+        Self { written, error }
+    }
+
+    /// Records written before the failure.
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    pub fn error(&self) -> &IoError<ParserError> {
+        &self.error
+    }
+
+    pub fn into_inner(self) -> IoError<ParserError> {
+        self.error
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.error.kind()
+    }
+}
+
+impl Error for BatchError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl MsrfError for BatchError {
+    fn kind(&self) -> ErrorKind {
+        BatchError::kind(self)
+    }
+}
+
+impl Display for BatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "failed to write record after {} written", self.written)
+    }
+}
+
+impl From<BatchError> for IoError<ParserError> {
+    fn from(value: BatchError) -> Self {
+        value.error
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn positioned_error() {
+        let position = Position::new(1234, 5)
+            .with_id(RecordId::new(1, 2))
+            .with_path([RecordId::new(3, 4), RecordId::new(5, 6)]);
+        let error = ReadError::new(ParserError::Guard(42), position);
+
+        assert_eq!(error.kind(), ErrorKind::Guard);
+        assert_eq!(error.position().offset(), 1234);
+        assert_eq!(
+            error.to_string(),
+            "failed at offset 1234 (record 5, id 0x0001:0x0002, path 0x0003:0x0004/0x0005:0x0006)"
+        );
+        let source = error.source().expect("no source");
+        assert_eq!(source.to_string(), ParserError::Guard(42).to_string());
+        assert!(source.downcast_ref::<IoError<ParserError>>().is_some());
+        let source = source.source().expect("no source");
+        assert!(source.downcast_ref::<ParserError>().is_some());
+        assert!(source.source().is_none());
+
+        let error = ReadError::new(std::io::Error::other("oops"), Position::new(0, 0));
+        let chain: Vec<_> = std::iter::successors(error.source(), |e| (*e).source()).collect();
+        assert_eq!(chain.len(), 2);
+        assert_eq!(
+            chain[1]
+                .downcast_ref::<std::io::Error>()
+                .map(ToString::to_string),
+            Some("oops".to_string())
+        );
+    }
+
+    #[test]
+    fn io_error_kind() {
+        let error: IoError<ParserError> = std::io::Error::other("oops").into();
+        assert_eq!(error.kind(), ErrorKind::Io);
+        let error: IoError<ParserError> = ParserError::IsEos.into();
+        assert_eq!(error.kind(), ErrorKind::IsEos);
+
+        let error: IoError<ParserError> = BuildError::Version {
+            expected: 1,
+            found: 2,
+        }
+        .into();
+        assert_eq!(error.kind(), ErrorKind::Unsupported);
+        assert!(matches!(
+            error,
+            IoError::Parser(ParserError::Version {
+                expected: 1,
+                found: 2
+            })
+        ));
+    }
+}
@@ -0,0 +1,342 @@
+#[cfg(feature = "reader")]
+use std::io::Read;
+
+#[cfg(feature = "signatures")]
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
+
+use crate::{RecordId, RecordMeta, trailer::DIGEST_LEN};
+#[cfg(feature = "reader")]
+use crate::{
+    codec::DesOptions,
+    error::{IoError, ParserError},
+    io::ReadExt,
+};
+
+pub const SIGNATURE_LEN: usize = 64;
+
+/// Id of the digest records written by streams declaring [`crate::Features::INTEGRITY`], which
+/// are verified and consumed by [`crate::reader::MsrfReader`] rather than returned.
+///
+/// Each holds the SHA-256 of the stream from the header up to and including its own meta, and
+/// the final (preceding EoS) may be followed by an Ed25519 signature of that digest. Writers of
+/// such streams refuse user records with this id (other than containers).
+pub const DIGEST_RECORD: RecordId = RecordId::new(0, 0x7FFF);
+
+/// Options for streams declaring [`crate::Features::INTEGRITY`], see
+/// [`crate::writer::MsrfWriterBuilder::integrity`].
+#[derive(Debug, Clone, Default)]
+pub struct IntegrityOptions {
+    pub(crate) interval: u64,
+    #[cfg(feature = "signatures")]
+    pub(crate) signing_key: Option<SigningKey>,
+}
+
+impl IntegrityOptions {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes a digest once at least `interval` records have been written since the last,
+    /// limiting how many records a reader must read before detecting a modification.
+    ///
+    /// Digests are only written between top level records, and if 0 (the default) only once
+    /// before EoS.
+    #[must_use]
+    pub fn interval(mut self, interval: u64) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Signs the final digest.
+    #[cfg(feature = "signatures")]
+    #[must_use]
+    pub fn signing_key(mut self, signing_key: SigningKey) -> Self {
+        self.signing_key = Some(signing_key);
+        self
+    }
+
+    #[cfg(all(feature = "writer", feature = "signatures"))]
+    fn has_signing_key(&self) -> bool {
+        self.signing_key.is_some()
+    }
+
+    #[cfg(all(feature = "writer", not(feature = "signatures")))]
+    fn has_signing_key(&self) -> bool {
+        false
+    }
+
+    #[cfg(all(feature = "writer", feature = "signatures"))]
+    fn signature(&self, digest: &[u8; DIGEST_LEN]) -> Option<[u8; SIGNATURE_LEN]> {
+        use ed25519_dalek::Signer;
+        self.signing_key
+            .as_ref()
+            .map(|key| key.sign(digest).to_bytes())
+    }
+
+    #[cfg(all(feature = "writer", not(feature = "signatures")))]
+    fn signature(&self, _digest: &[u8; DIGEST_LEN]) -> Option<[u8; SIGNATURE_LEN]> {
+        None
+    }
+}
+
+#[cfg(feature = "writer")]
+#[derive(Debug, Clone)]
+pub(crate) struct IntegrityWriter {
+    pub(crate) options: IntegrityOptions,
+    // Records written since the previous digest
+    pub(crate) pending: u64,
+}
+
+#[cfg(feature = "writer")]
+impl IntegrityWriter {
+    pub(crate) fn new(options: IntegrityOptions) -> Self {
+        Self {
+            options,
+            pending: 0,
+        }
+    }
+
+    pub(crate) fn is_due(&self, depth: usize) -> bool {
+        depth == 0 && self.options.interval > 0 && self.pending >= self.options.interval
+    }
+
+    // Only the final digest is signed
+    fn is_signed(&self, is_final: bool) -> bool {
+        is_final && self.options.has_signing_key()
+    }
+
+    pub(crate) fn meta(&self, is_final: bool) -> RecordMeta {
+        let signature_len = if self.is_signed(is_final) {
+            SIGNATURE_LEN
+        } else {
+            0
+        };
+        DIGEST_RECORD.into_meta((DIGEST_LEN + signature_len) as u64)
+    }
+
+    pub(crate) fn payload(&self, digest: [u8; DIGEST_LEN], is_final: bool) -> Vec<u8> {
+        let mut payload = digest.to_vec();
+        if is_final && let Some(signature) = self.options.signature(&digest) {
+            payload.extend_from_slice(&signature);
+        }
+        payload
+    }
+}
+
+#[cfg(feature = "reader")]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct IntegrityReader {
+    // Records read up to the most recently verified digest
+    pub(crate) verified: u64,
+    pub(crate) pending: bool,
+    pub(crate) signed: bool,
+}
+
+#[cfg(feature = "reader")]
+impl IntegrityReader {
+    /// Verifies the payload of a digest record against `digest`, that of the stream up to and
+    /// including its meta.
+    pub(crate) fn verify(
+        &mut self,
+        meta: &RecordMeta,
+        mut payload: impl Read,
+        digest: [u8; DIGEST_LEN],
+        records: u64,
+        options: &DesOptions,
+    ) -> Result<(), IoError<ParserError>> {
+        let signed = match meta.len() as usize {
+            DIGEST_LEN => false,
+            len if len == DIGEST_LEN + SIGNATURE_LEN => true,
+            len => return Err(IoError::Parser(ParserError::Length(len as u64))),
+        };
+
+        let expected: [u8; DIGEST_LEN] = payload.read_chunk()?;
+        let signature: Option<[u8; SIGNATURE_LEN]> =
+            signed.then(|| payload.read_chunk()).transpose()?;
+        if expected != digest {
+            return Err(IoError::Parser(ParserError::Digest(self.verified)));
+        }
+
+        // Intermediate digests are unsigned, while the final is checked by `finish`
+        #[cfg(feature = "signatures")]
+        if let (Some(key), Some(signature)) = (&options.verifying_key, signature) {
+            let signature = ed25519_dalek::Signature::from_bytes(&signature);
+            if key.verify_strict(&digest, &signature).is_err() {
+                return Err(IoError::Parser(ParserError::Signature(self.verified)));
+            }
+        }
+        #[cfg(not(feature = "signatures"))]
+        let _ = (signature, options);
+
+        self.verified = records;
+        self.pending = false;
+        self.signed = signed;
+        Ok(())
+    }
+
+    /// Called on EoS (also for streams without digests), every record must be covered by a
+    /// digest, and if a verifying key was given, the final digest must be signed.
+    pub(crate) fn finish(&self, options: &DesOptions) -> Result<(), ParserError> {
+        #[cfg(feature = "signatures")]
+        let require_signed = options.verifying_key.is_some();
+        #[cfg(not(feature = "signatures"))]
+        let require_signed = {
+            let _ = options;
+            false
+        };
+
+        if self.pending {
+            Err(ParserError::Digest(self.verified))
+        } else if require_signed && !self.signed {
+            Err(ParserError::Signature(self.verified))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(all(test, feature = "reader", feature = "writer"))]
+mod test {
+    use std::io::{Cursor, Read};
+
+    use super::*;
+    use crate::{
+        EXTENDED_HEADER_VERSION,
+        codec::DesOptions,
+        error::{ErrorKind, IoError, ReadError},
+        reader::MsrfReaderBuilder,
+        rotate::test::Value,
+        writer::MsrfWriterBuilder,
+    };
+
+    // Returns the stream and the payload offset of each record
+    fn write_stream(options: IntegrityOptions, records: u8) -> (Vec<u8>, Vec<u64>) {
+        let mut wtr = MsrfWriterBuilder::new()
+            .version(EXTENDED_HEADER_VERSION)
+            .integrity(options)
+            .build(Vec::new())
+            .expect("unsupported version")
+            .initialise()
+            .expect("failed to initialise");
+        let mut payloads = Vec::new();
+        for i in 0..records {
+            wtr.write_record(Value(i), 1).expect("failed write");
+            payloads.extend(wtr.record_span().map(|span| span.payload));
+        }
+        (wtr.finish().expect("failed finish"), payloads)
+    }
+
+    fn read_stream(data: Vec<u8>, options: DesOptions) -> Result<u64, ReadError> {
+        let mut rdr = MsrfReaderBuilder::new()
+            .options(options)
+            .build(Cursor::new(data))
+            .expect("failed to build");
+        loop {
+            let res = rdr.read_record().map(|(_, mut user_rdr)| {
+                user_rdr.read_to_end(&mut Vec::new()).expect("io fail");
+            });
+            match res {
+                Ok(()) => {}
+                Err(e) if e.kind() == ErrorKind::IsEos => return Ok(rdr.records()),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn digest_error(err: &ReadError) -> Option<u64> {
+        match err.error() {
+            IoError::Parser(ParserError::Digest(n)) => Some(*n),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn verify_integrity() {
+        let (data, _) = write_stream(IntegrityOptions::new().interval(2), 5);
+        let (unchecked, _) = write_stream(IntegrityOptions::new(), 5);
+        // Two intermediate digests, each a meta, digest and guard
+        assert!(data.len() > unchecked.len() + 2 * (DIGEST_LEN + 1));
+        assert_eq!(
+            read_stream(data, DesOptions::new()).expect("failed verify"),
+            5
+        );
+        assert_eq!(
+            read_stream(unchecked, DesOptions::new()).expect("failed verify"),
+            5
+        );
+    }
+
+    #[test]
+    fn verify_integrity_altered() {
+        // Detected by the digest following the fourth record
+        let (mut altered, payloads) = write_stream(IntegrityOptions::new().interval(2), 5);
+        altered[payloads[2] as usize] ^= 1;
+        let err = read_stream(altered, DesOptions::new()).expect_err("succeeded verify");
+        assert_eq!(err.kind(), ErrorKind::Integrity);
+        assert_eq!(digest_error(&err), Some(2));
+    }
+
+    #[test]
+    fn verify_integrity_missing() {
+        use crate::codec::{AnySerialiser, RawSerialiser};
+
+        // Drop the final digest record, preceding EoS
+        let (data, _) = write_stream(IntegrityOptions::new(), 3);
+        let mut meta = Vec::new();
+        AnySerialiser::new_default(crate::CURRENT_VERSION)
+            .expect("unsupported version")
+            .write_meta(DIGEST_RECORD.into_meta(DIGEST_LEN as u64), &mut meta)
+            .expect("ser fail");
+        let start = data
+            .windows(meta.len())
+            .rposition(|w| w == meta)
+            .expect("missing digest");
+        let mut truncated = data[..start].to_vec();
+        truncated.extend_from_slice(&data[start + meta.len() + DIGEST_LEN + 1..]);
+
+        let err = read_stream(truncated, DesOptions::new()).expect_err("succeeded verify");
+        assert_eq!(digest_error(&err), Some(0));
+    }
+
+    #[cfg(feature = "signatures")]
+    #[test]
+    fn verify_signature() {
+        let signing_key = SigningKey::from_bytes(&[7; 32]);
+        let options = IntegrityOptions::new()
+            .interval(2)
+            .signing_key(signing_key.clone());
+        let (data, _) = write_stream(options, 3);
+        let key = signing_key.verifying_key();
+        assert_eq!(
+            read_stream(data.clone(), DesOptions::new().verifying_key(key)).expect("failed verify"),
+            3
+        );
+
+        let signature = |err: ReadError| match err.error() {
+            IoError::Parser(ParserError::Signature(n)) => Some(*n),
+            _ => None,
+        };
+        let other = SigningKey::from_bytes(&[8; 32]).verifying_key();
+        let err = read_stream(data, DesOptions::new().verifying_key(other))
+            .expect_err("succeeded verify");
+        assert_eq!(signature(err), Some(2));
+
+        let (unsigned, _) = write_stream(IntegrityOptions::new(), 3);
+        let err = read_stream(unsigned, DesOptions::new().verifying_key(key))
+            .expect_err("succeeded verify");
+        assert_eq!(signature(err), Some(3));
+
+        // Nor may a key be satisfied by omitting digests altogether
+        let mut wtr = MsrfWriterBuilder::new()
+            .build(Vec::new())
+            .expect("unsupported version")
+            .initialise()
+            .expect("failed to initialise");
+        wtr.write_record(Value(0), 1).expect("failed write");
+        let undeclared = wtr.finish().expect("failed finish");
+        let err = read_stream(undeclared, DesOptions::new().verifying_key(key))
+            .expect_err("succeeded verify");
+        assert_eq!(signature(err), Some(0));
+    }
+}
@@ -1,5 +1,18 @@
 #![allow(clippy::len_without_is_empty)]
-use std::io::{Error as IoError, Read, Result as IoResult, Take, Write, copy, sink};
+#[cfg(feature = "reader")]
+use std::io::BufRead;
+#[cfg(feature = "writer")]
+use std::io::IoSlice;
+use std::io::{Error as IoError, ErrorKind, Read, Result as IoResult, Write};
+use std::time::{Duration, Instant};
+
+#[cfg(any(feature = "reader", feature = "writer"))]
+use sha2::Digest;
+
+#[cfg(feature = "writer")]
+use crate::trailer::DIGEST_LEN;
+#[cfg(any(feature = "reader", feature = "writer"))]
+use crate::trailer::StreamDigest;
 
 const TAG_CONTAINS_DATA_LEN: usize = 7;
 
@@ -114,42 +127,552 @@ impl<W: Write> WriteExt for W {
     }
 }
 
-pub struct RecordChunk<'a, R: Read>(Take<&'a mut R>);
+#[cfg(feature = "reader")]
+/// Payload bytes of the record being read that are yet to be consumed, and the error (if any)
+/// which stopped a dropped [`RecordChunk`] from skipping them.
+#[derive(Debug, Default)]
+pub(crate) struct PayloadState {
+    pub(crate) remaining: u64,
+    pub(crate) error: Option<IoError>,
+}
 
+#[cfg(feature = "reader")]
+// Reads and discards `remaining` bytes, tracking progress so a failed skip may be resumed
+pub(crate) fn skip<R: Read>(mut rdr: R, remaining: &mut u64) -> IoResult<()> {
+    let mut buf = [0; 4096];
+    while *remaining > 0 {
+        let len = buf
+            .len()
+            .min(usize::try_from(*remaining).unwrap_or(usize::MAX));
+        match rdr.read(&mut buf[..len]) {
+            Ok(0) => return Err(IoError::from(ErrorKind::UnexpectedEof)),
+            Ok(read) => *remaining -= read as u64,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "reader")]
+// Appends `remaining` bytes to `buf`, reserving at most `step` bytes ahead of those read and
+// tracking progress so a failed read may be skipped. The zeroed tail is reused until filled.
+pub(crate) fn read_into<R: Read>(
+    mut rdr: R,
+    buf: &mut Vec<u8>,
+    remaining: &mut u64,
+    step: usize,
+) -> IoResult<()> {
+    let mut filled = buf.len();
+    let mut res = Ok(());
+    while *remaining > 0 {
+        if filled == buf.len() {
+            let len = step.min(usize::try_from(*remaining).unwrap_or(usize::MAX));
+            buf.resize(filled + len, 0);
+        }
+        match rdr.read(&mut buf[filled..]) {
+            Ok(0) => {
+                res = Err(IoError::from(ErrorKind::UnexpectedEof));
+                break;
+            }
+            Ok(read) => {
+                filled += read;
+                *remaining -= read as u64;
+            }
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => {
+                res = Err(e);
+                break;
+            }
+        }
+    }
+    buf.truncate(filled);
+    res
+}
+
+#[cfg(feature = "reader")]
+/// Payload of a record, any of which is left unread being skipped when dropped.
+///
+/// Errors while skipping are returned by the next read of [`crate::reader::MsrfReader`].
+pub struct RecordChunk<'a, R: Read>(Chunk<'a, R>);
+
+#[cfg(feature = "reader")]
+enum Chunk<'a, R> {
+    Stream(DigestReader<'a, &'a mut ReadAhead<R>>, &'a mut PayloadState),
+    // Already read from the stream (e.g. decrypted)
+    #[cfg(feature = "encryption")]
+    Buffered(&'a [u8]),
+}
+
+#[cfg(feature = "reader")]
 impl<'a, R: Read> RecordChunk<'a, R> {
-    pub(crate) fn new(rdr: &'a mut R, limit: u64) -> Self {
-        Self(rdr.take(limit))
+    pub(crate) fn new(
+        rdr: &'a mut ReadAhead<R>,
+        limit: u64,
+        digest: Option<&'a mut StreamDigest>,
+        state: &'a mut PayloadState,
+    ) -> Self {
+        state.remaining = limit;
+        state.error = None;
+        Self(Chunk::Stream(DigestReader::new(rdr, digest), state))
+    }
+
+    #[cfg(feature = "encryption")]
+    pub(crate) fn buffered(buf: &'a [u8]) -> Self {
+        Self(Chunk::Buffered(buf))
     }
 
     #[must_use]
     pub fn len(&self) -> u64 {
-        self.0.limit()
+        match &self.0 {
+            Chunk::Stream(_, state) => state.remaining,
+            #[cfg(feature = "encryption")]
+            Chunk::Buffered(buf) => buf.len() as u64,
+        }
     }
 
     #[must_use]
     pub fn is_empty(&self) -> bool {
-        self.0.limit() == 0
+        self.len() == 0
     }
 
     pub(crate) fn drain(&mut self) -> IoResult<()> {
-        if self.0.limit() > 0 {
-            copy(&mut self.0, &mut sink()).map(|_| ())
-        } else {
-            Ok(())
+        match &mut self.0 {
+            Chunk::Stream(rdr, state) => skip(rdr, &mut state.remaining),
+            #[cfg(feature = "encryption")]
+            Chunk::Buffered(buf) => {
+                *buf = &[];
+                Ok(())
+            }
         }
     }
 }
 
+#[cfg(feature = "reader")]
 impl<R: Read> Read for RecordChunk<'_, R> {
     fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
-        self.0.read(buf)
+        match &mut self.0 {
+            Chunk::Stream(rdr, state) => {
+                let len = buf
+                    .len()
+                    .min(usize::try_from(state.remaining).unwrap_or(usize::MAX));
+                // The stream may not have more data yet (e.g. when followed)
+                if len == 0 {
+                    return Ok(0);
+                }
+                let read = rdr.read(&mut buf[..len])?;
+                state.remaining -= read as u64;
+                Ok(read)
+            }
+            #[cfg(feature = "encryption")]
+            Chunk::Buffered(rdr) => rdr.read(buf),
+        }
     }
 }
 
+#[cfg(feature = "reader")]
 impl<R: Read> Drop for RecordChunk<'_, R> {
     fn drop(&mut self) {
         // BufWriter<W> drop impl also performs IO (flushing) on drop, we shall pretend this is normal
-        let _res = self.drain();
+        if let Err(e) = self.drain()
+            && let Chunk::Stream(_, state) = &mut self.0
+        {
+            state.error = Some(e);
+        }
+    }
+}
+
+#[cfg(feature = "reader")]
+/// Passes reads through to `R`, feeding the bytes returned into `digest` (if any).
+pub(crate) struct DigestReader<'a, R> {
+    rdr: R,
+    digest: Option<&'a mut StreamDigest>,
+}
+
+#[cfg(feature = "reader")]
+impl<'a, R> DigestReader<'a, R> {
+    pub(crate) fn new(rdr: R, digest: Option<&'a mut StreamDigest>) -> Self {
+        Self { rdr, digest }
+    }
+}
+
+#[cfg(feature = "reader")]
+impl<R: Read> Read for DigestReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let read = self.rdr.read(buf)?;
+        if let Some(digest) = &mut self.digest {
+            digest.update(&buf[..read]);
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(feature = "reader")]
+/// Read-ahead buffer of [`crate::reader::MsrfReader`], from which metas are decoded in one pass.
+///
+/// Reads at least as large as the buffer bypass it once it is empty (e.g. large payloads).
+pub(crate) struct ReadAhead<R> {
+    rdr: R,
+    buf: Box<[u8]>,
+    pos: usize,
+    filled: usize,
+}
+
+#[cfg(feature = "reader")]
+impl<R> ReadAhead<R> {
+    const CAPACITY: usize = 8 * 1024;
+
+    pub(crate) fn new(rdr: R) -> Self {
+        Self {
+            rdr,
+            buf: vec![0; Self::CAPACITY].into_boxed_slice(),
+            pos: 0,
+            filled: 0,
+        }
+    }
+
+    // Wraps the underlying reader, keeping any bytes already buffered
+    pub(crate) fn map<T>(self, f: impl FnOnce(R) -> T) -> ReadAhead<T> {
+        ReadAhead {
+            rdr: f(self.rdr),
+            buf: self.buf,
+            pos: self.pos,
+            filled: self.filled,
+        }
+    }
+}
+
+#[cfg(feature = "reader")]
+impl<R: Read> Read for ReadAhead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        if self.pos == self.filled && buf.len() >= self.buf.len() {
+            return self.rdr.read(buf);
+        }
+
+        let read = self.fill_buf()?.read(buf)?;
+        self.consume(read);
+        Ok(read)
+    }
+}
+
+#[cfg(feature = "reader")]
+impl<R: Read> BufRead for ReadAhead<R> {
+    fn fill_buf(&mut self) -> IoResult<&[u8]> {
+        while self.pos == self.filled {
+            match self.rdr.read(&mut self.buf) {
+                Ok(read) => {
+                    self.pos = 0;
+                    self.filled = read;
+                    if read == 0 {
+                        break;
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(&self.buf[self.pos..self.filled])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.filled);
+    }
+}
+
+#[cfg(feature = "writer")]
+/// Writer underlying [`crate::writer::MsrfWriter`], through which payloads are encoded by
+/// [`crate::codec::IntoData`].
+///
+/// Each record is assembled in a scratch buffer and written with a single write, unless it
+/// outgrows the buffer (in which case the buffer is written alongside the write overfilling it).
+pub struct RecordSink<W> {
+    wtr: W,
+    digest: Option<StreamDigest>,
+    scratch: Vec<u8>,
+    // Writes are buffered in `scratch` rather than passed through, see `begin`
+    buffering: bool,
+    // `scratch` is only written out by `end`, see `hold`
+    held: bool,
+    // Bytes have reached `wtr` since `begin`, so a failed record may be partially written
+    emitted: bool,
+}
+
+#[cfg(feature = "writer")]
+impl<W> RecordSink<W> {
+    const SCRATCH_LEN: usize = 8 * 1024;
+
+    pub(crate) fn new(wtr: W, digest: bool) -> Self {
+        Self {
+            wtr,
+            digest: digest.then(StreamDigest::new),
+            scratch: Vec::with_capacity(Self::SCRATCH_LEN),
+            buffering: false,
+            held: false,
+            emitted: false,
+        }
+    }
+
+    // Buffers writes until `end`, discarding any left by a failed record
+    pub(crate) fn begin(&mut self) {
+        self.scratch.clear();
+        self.buffering = true;
+        self.held = false;
+        self.emitted = false;
+    }
+
+    pub(crate) fn is_emitted(&self) -> bool {
+        self.emitted
+    }
+
+    // Buffers writes regardless of length until `release` (so are never written out or digested),
+    // returning the offset they start at
+    #[cfg(feature = "encryption")]
+    pub(crate) fn hold(&mut self) -> usize {
+        self.buffering = true;
+        self.held = true;
+        self.scratch.len()
+    }
+
+    #[cfg(feature = "encryption")]
+    pub(crate) fn release(&mut self, offset: usize) -> Vec<u8> {
+        self.held = false;
+        self.scratch.split_off(offset)
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.wtr
+    }
+
+    pub fn into_inner(self) -> W {
+        self.wtr
+    }
+
+    // Digest of the bytes written so far (including those buffered), leaving it running
+    pub(crate) fn digest_so_far(&self) -> Option<[u8; DIGEST_LEN]> {
+        let mut digest = self.digest.clone()?;
+        digest.update(&self.scratch);
+        Some(digest.finalize().into())
+    }
+
+    // Ends the digest, bytes written afterwards are not included
+    pub(crate) fn finalize_digest(&mut self) -> Option<[u8; DIGEST_LEN]> {
+        let mut digest = self.digest.take()?;
+        digest.update(&self.scratch);
+        Some(digest.finalize().into())
+    }
+}
+
+#[cfg(feature = "writer")]
+impl<W: Write> RecordSink<W> {
+    // Writes out anything buffered since `begin`, passing further writes through
+    pub(crate) fn end(&mut self) -> IoResult<()> {
+        self.buffering = false;
+        self.held = false;
+        self.write_out(&[])
+    }
+
+    // Writes `scratch` followed by `buf` in as few writes as `W` allows, emptying `scratch`
+    // Bytes are digested once written out, so those of a discarded record are not
+    fn write_out(&mut self, buf: &[u8]) -> IoResult<()> {
+        if let Some(digest) = &mut self.digest {
+            digest.update(&self.scratch);
+            digest.update(buf);
+        }
+        self.emitted |= !self.scratch.is_empty() || !buf.is_empty();
+        let mut slices = [IoSlice::new(&self.scratch), IoSlice::new(buf)];
+        let mut slices = &mut slices[..];
+        IoSlice::advance_slices(&mut slices, 0);
+        while !slices.is_empty() {
+            match self.wtr.write_vectored(slices) {
+                Ok(0) => return Err(IoError::from(ErrorKind::WriteZero)),
+                Ok(written) => IoSlice::advance_slices(&mut slices, written),
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        self.scratch.clear();
+        Ok(())
+    }
+}
+
+#[cfg(feature = "writer")]
+impl<W: Write> Write for RecordSink<W> {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        if !self.buffering {
+            self.emitted = true;
+            let written = self.wtr.write(buf)?;
+            if let Some(digest) = &mut self.digest {
+                digest.update(&buf[..written]);
+            }
+            return Ok(written);
+        }
+
+        if self.held || self.scratch.len() + buf.len() <= Self::SCRATCH_LEN {
+            self.scratch.extend_from_slice(buf);
+        } else {
+            self.write_out(buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        self.wtr.flush()
+    }
+}
+
+/// Passes reads through to `R` while tallying the number of bytes returned.
+pub struct CountingReader<R> {
+    rdr: R,
+    count: u64,
+}
+
+impl<R: Read> CountingReader<R> {
+    pub fn new(rdr: R) -> Self {
+        Self { rdr, count: 0 }
+    }
+
+    #[must_use]
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.rdr
+    }
+
+    pub fn into_inner(self) -> R {
+        self.rdr
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let read = self.rdr.read(buf)?;
+        self.count += read as u64;
+        Ok(read)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FollowOptions {
+    poll_interval: Duration,
+    timeout: Option<Duration>,
+}
+
+impl FollowOptions {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delay between attempts to read more data after reaching EOF.
+    #[must_use]
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Maximum time without new data before failing with [`ErrorKind::TimedOut`].
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+impl Default for FollowOptions {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_millis(100),
+            timeout: None,
+        }
+    }
+}
+
+/// Treats EOF as "no data yet", polling `R` until more data arrives (e.g. a file still being
+/// written by another process).
+pub struct Follow<R> {
+    rdr: R,
+    options: FollowOptions,
+}
+
+impl<R: Read> Follow<R> {
+    pub fn new(rdr: R, options: FollowOptions) -> Self {
+        Self { rdr, options }
+    }
+
+    pub fn options(&self) -> &FollowOptions {
+        &self.options
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.rdr
+    }
+
+    pub fn into_inner(self) -> R {
+        self.rdr
+    }
+}
+
+impl<R: Read> Read for Follow<R> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let start = Instant::now();
+        loop {
+            match self.rdr.read(buf)? {
+                0 => {
+                    if self
+                        .options
+                        .timeout
+                        .is_some_and(|timeout| start.elapsed() >= timeout)
+                    {
+                        return Err(IoError::new(ErrorKind::TimedOut, "no data before timeout"));
+                    }
+                    std::thread::sleep(self.options.poll_interval);
+                }
+                read => return Ok(read),
+            }
+        }
+    }
+}
+
+/// Passes writes through to `W` while tallying the number of bytes accepted.
+pub struct CountingWriter<W> {
+    wtr: W,
+    count: u64,
+}
+
+impl<W: Write> CountingWriter<W> {
+    pub fn new(wtr: W) -> Self {
+        Self { wtr, count: 0 }
+    }
+
+    #[must_use]
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.wtr
+    }
+
+    pub fn into_inner(self) -> W {
+        self.wtr
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        let written = self.wtr.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        self.wtr.flush()
     }
 }
 
@@ -192,4 +715,34 @@ mod test {
         harness(0xFFFFFFFFFFFFFF); // 2^56-1
         harness(0xFFFFFFFFFFFFFFFF); // 2^64
     }
+
+    #[cfg(feature = "reader")]
+    #[test]
+    fn read_into_short_reads() {
+        struct Short<'a>(&'a [u8]);
+
+        impl Read for Short<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+                let len = buf.len().min(self.0.len()).min(3);
+                buf[..len].copy_from_slice(&self.0[..len]);
+                self.0 = &self.0[len..];
+                Ok(len)
+            }
+        }
+
+        let data: Vec<u8> = (1..=20).collect();
+        let mut buf = vec![0xFF];
+        let mut remaining = 20;
+        read_into(Short(&data), &mut buf, &mut remaining, 8).unwrap();
+        assert_eq!(remaining, 0);
+        assert_eq!(buf[0], 0xFF);
+        assert_eq!(&buf[1..], &data[..]);
+
+        let mut buf = Vec::new();
+        let mut remaining = 30;
+        let err = read_into(Short(&data), &mut buf, &mut remaining, 8).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(remaining, 10);
+        assert_eq!(buf, data);
+    }
 }
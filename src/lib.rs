@@ -1,79 +1,283 @@
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
+use std::ops::BitOr;
+use std::time::{Duration, SystemTime};
 
 use crate::io::SizedValue;
 
+#[cfg(feature = "reader")]
+pub mod checkpoint;
 #[cfg(any(feature = "reader", feature = "writer"))]
 pub mod codec;
+#[cfg(all(feature = "reader", feature = "writer"))]
+pub mod convert;
+#[cfg(feature = "encryption")]
+pub mod encryption;
 pub mod error;
+#[cfg(any(feature = "reader", feature = "writer"))]
+pub mod integrity;
 pub mod io;
+#[cfg(feature = "writer")]
+pub mod parallel;
 #[cfg(feature = "reader")]
 pub mod reader;
 #[cfg(feature = "writer")]
+pub mod rotate;
+#[cfg(feature = "reader")]
+pub mod segment;
+#[cfg(any(feature = "reader", feature = "writer"))]
+pub mod trailer;
+#[cfg(feature = "writer")]
 pub mod writer;
 
+/// Source id marking EoS in every version.
 pub const RECORD_EOS: u16 = u16::MAX;
-pub const CURRENT_VERSION: u16 = 0;
+pub const CURRENT_VERSION: u16 = 2;
+/// Version written unless another is requested, the most widely readable.
+pub const DEFAULT_VERSION: u16 = 0;
+/// First version whose header carries [`Features`] and fields, see [`Header`].
+pub const EXTENDED_HEADER_VERSION: u16 = 2;
+#[cfg(any(feature = "reader", feature = "writer"))]
 pub(crate) const TYPE_CONTAINER_MASK: u16 = 0x8000;
 
 pub trait ConstAssignedId {
-    const TYPE_ID: u16;
+    const TYPE_ID: u64;
 }
 
 pub trait AssignedId {
-    fn typ_id(&self) -> u16;
+    fn typ_id(&self) -> u64;
 }
 
 impl<T> AssignedId for T
 where
     T: ConstAssignedId,
 {
-    fn typ_id(&self) -> u16 {
+    fn typ_id(&self) -> u64 {
         T::TYPE_ID
     }
 }
 
 pub trait IntoMetadata<S>: AssignedId + SizedValue<S> {
-    fn meta(&self, ser: &S, source_id: u16) -> RecordMeta {
+    fn meta(&self, ser: &S, source_id: u64) -> RecordMeta {
         RecordMeta::new(source_id, self.typ_id(), self.encoded_len(ser) as u64)
     }
 }
 
+/// Stream-level feature bits declared by an extended header.
+///
+/// A reader must refuse a stream requiring a feature it does not support, while optional
+/// features may be ignored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Features(u64);
+
+impl Features {
+    pub const NONE: Self = Self(0);
+    /// A [`trailer::Trailer`] follows EoS.
+    pub const TRAILER: Self = Self(1 << 3);
+    /// Digest records are interleaved, see [`integrity::DIGEST_RECORD`].
+    pub const INTEGRITY: Self = Self(1 << 4);
+    /// Payloads of the sources listed by [`Header::FIELD_ENCRYPTED`] are encrypted.
+    pub const ENCRYPTION: Self = Self(1 << 5);
+    /// Features this library can read when required.
+    pub const SUPPORTED: Self = Self(Self::TRAILER.0 | Self::INTEGRITY.0);
+
+    #[must_use]
+    pub const fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    #[must_use]
+    pub const fn bits(&self) -> u64 {
+        self.0
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    #[must_use]
+    pub const fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Features in `self` but not in `other`.
+    #[must_use]
+    pub const fn difference(&self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+}
+
+impl BitOr for Features {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Header {
     pub version: u16,
+    pub(crate) required: Features,
+    pub(crate) optional: Features,
+    pub(crate) fields: Vec<(u64, Vec<u8>)>,
 }
 
 impl Header {
+    /// 16 byte stream UUID.
+    pub const FIELD_UUID: u64 = 1;
+    /// Creation time as a PVarint of microseconds since the Unix epoch.
+    pub const FIELD_CREATED: u64 = 2;
+    /// UTF-8 name of the producing application.
+    pub const FIELD_PRODUCER: u64 = 3;
+    /// Source ids whose payloads are encrypted, as consecutive PVarints.
+    pub const FIELD_ENCRYPTED: u64 = 4;
+
     #[must_use] 
     pub const fn new(version: u16) -> Self {
-        Self { version }
+        Self {
+            version,
+            required: Features::NONE,
+            optional: Features::NONE,
+            fields: Vec::new(),
+        }
     }
 
     #[must_use] 
     pub const fn version(&self) -> u16 {
         self.version
     }
+
+    /// Features a reader must support to read the stream.
+    #[must_use]
+    pub fn with_required(mut self, features: Features) -> Self {
+        self.required = features;
+        self
+    }
+
+    /// Features a reader may ignore.
+    #[must_use]
+    pub fn with_optional(mut self, features: Features) -> Self {
+        self.optional = features;
+        self
+    }
+
+    /// Sets the field `tag`, replacing any previous value.
+    #[must_use]
+    pub fn with_field(mut self, tag: u64, value: Vec<u8>) -> Self {
+        match self.fields.iter_mut().find(|(t, _)| *t == tag) {
+            Some((_, v)) => *v = value,
+            None => self.fields.push((tag, value)),
+        }
+        self
+    }
+
+    #[must_use]
+    pub fn with_uuid(self, uuid: [u8; 16]) -> Self {
+        self.with_field(Self::FIELD_UUID, uuid.to_vec())
+    }
+
+    /// Times before the Unix epoch are clamped to it.
+    #[must_use]
+    pub fn with_created(self, created: SystemTime) -> Self {
+        let micros = created
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| u64::try_from(d.as_micros()).unwrap_or(u64::MAX));
+        self.with_field(
+            Self::FIELD_CREATED,
+            io::PVarint::encode(micros).as_slice().to_vec(),
+        )
+    }
+
+    #[must_use]
+    pub fn with_producer(self, producer: &str) -> Self {
+        self.with_field(Self::FIELD_PRODUCER, producer.as_bytes().to_vec())
+    }
+
+    #[must_use]
+    pub const fn required(&self) -> Features {
+        self.required
+    }
+
+    #[must_use]
+    pub const fn optional(&self) -> Features {
+        self.optional
+    }
+
+    #[must_use]
+    pub fn field(&self, tag: u64) -> Option<&[u8]> {
+        self.fields
+            .iter()
+            .find(|(t, _)| *t == tag)
+            .map(|(_, v)| v.as_slice())
+    }
+
+    /// Fields in the order they were written.
+    pub fn fields(&self) -> impl Iterator<Item = (u64, &[u8])> {
+        self.fields.iter().map(|(t, v)| (*t, v.as_slice()))
+    }
+
+    /// `None` if absent or not 16 bytes.
+    #[must_use]
+    pub fn uuid(&self) -> Option<[u8; 16]> {
+        self.field(Self::FIELD_UUID)?.try_into().ok()
+    }
+
+    /// `None` if absent or malformed.
+    #[must_use]
+    pub fn created(&self) -> Option<SystemTime> {
+        let mut value = self.field(Self::FIELD_CREATED)?;
+        let micros = io::ReadExt::read_varint(&mut value).ok()?;
+        SystemTime::UNIX_EPOCH.checked_add(Duration::from_micros(micros))
+    }
+
+    /// `None` if absent or not UTF-8.
+    #[must_use]
+    pub fn producer(&self) -> Option<&str> {
+        std::str::from_utf8(self.field(Self::FIELD_PRODUCER)?).ok()
+    }
+
+    /// `None` if absent or malformed.
+    #[must_use]
+    pub fn encrypted_sources(&self) -> Option<Vec<u64>> {
+        let mut value = self.field(Self::FIELD_ENCRYPTED)?;
+        let mut sources = Vec::new();
+        while !value.is_empty() {
+            sources.push(io::ReadExt::read_varint(&mut value).ok()?);
+        }
+        Some(sources)
+    }
+
+    /// Whether `features` are either required or optional.
+    #[must_use]
+    pub fn declares(&self, features: Features) -> bool {
+        (self.required | self.optional).contains(features)
+    }
+
+    /// Whether the header has content requiring [`EXTENDED_HEADER_VERSION`].
+    #[must_use]
+    pub fn is_extended(&self) -> bool {
+        !(self.required.is_empty() && self.optional.is_empty() && self.fields.is_empty())
+    }
 }
 
 impl Default for Header {
     fn default() -> Self {
-        Self {
-            version: CURRENT_VERSION,
-        }
+        Self::new(DEFAULT_VERSION)
     }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct RecordMeta {
-    pub(crate) source_id: u16,
-    pub(crate) type_id: u16,
+    pub(crate) source_id: u64,
+    pub(crate) type_id: u64,
     pub(crate) length: u64,
-    pub(crate) contained: Option<u16>,
+    pub(crate) contained: Option<u64>,
 }
 
 impl RecordMeta {
     #[must_use] 
-    pub const fn new(source_id: u16, type_id: u16, length: u64) -> Self {
+    pub const fn new(source_id: u64, type_id: u64, length: u64) -> Self {
         Self {
             length,
             contained: None,
@@ -83,7 +287,7 @@ impl RecordMeta {
     }
 
     #[must_use] 
-    pub const fn new_container(source_id: u16, type_id: u16, length: u64, contained: u16) -> Self {
+    pub const fn new_container(source_id: u64, type_id: u64, length: u64, contained: u64) -> Self {
         Self {
             source_id,
             type_id,
@@ -97,7 +301,7 @@ impl RecordMeta {
         Self {
             length: 0,
             contained: None,
-            source_id: RECORD_EOS,
+            source_id: RECORD_EOS as u64,
             type_id: 0,
         }
     }
@@ -114,16 +318,16 @@ impl RecordMeta {
 
     #[must_use] 
     pub const fn is_eos(&self) -> bool {
-        self.source_id == RECORD_EOS
+        self.source_id == RECORD_EOS as u64
     }
 
     #[must_use] 
-    pub const fn source_id(&self) -> u16 {
+    pub const fn source_id(&self) -> u64 {
         self.source_id
     }
 
     #[must_use] 
-    pub const fn type_id(&self) -> u16 {
+    pub const fn type_id(&self) -> u64 {
         self.type_id
     }
 
@@ -138,44 +342,137 @@ impl RecordMeta {
     }
 
     #[must_use] 
-    pub const fn contained(&self) -> Option<u16> {
+    pub const fn contained(&self) -> Option<u64> {
         self.contained
     }
 }
 
+/// Byte offsets of a record within a stream.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RecordSpan {
+    pub(crate) meta: u64,
+    pub(crate) payload: u64,
+    pub(crate) end: u64,
+}
+
+impl RecordSpan {
+    #[must_use]
+    pub const fn meta_offset(&self) -> u64 {
+        self.meta
+    }
+
+    #[must_use]
+    pub const fn payload_offset(&self) -> u64 {
+        self.payload
+    }
+
+    /// Offset one past the final payload byte (i.e. the offset of the guard).
+    #[must_use]
+    pub const fn payload_end(&self) -> u64 {
+        self.end
+    }
+
+    #[must_use]
+    pub const fn payload_len(&self) -> u64 {
+        self.end - self.payload
+    }
+}
+
+/// A record and its payload read in full, see [`reader::MsrfReader::into_records`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedRecord {
+    pub(crate) meta: RecordMeta,
+    pub(crate) payload: Vec<u8>,
+    pub(crate) parents: Vec<RecordId>,
+}
+
+impl OwnedRecord {
+    #[must_use]
+    pub fn new(id: RecordId, payload: Vec<u8>) -> Self {
+        Self {
+            meta: id.into_meta(payload.len() as u64),
+            payload,
+            parents: Vec::new(),
+        }
+    }
+
+    /// A `contained` of 0 makes a plain record, as empty containers are malformed.
+    #[must_use]
+    pub fn new_container(id: RecordId, payload: Vec<u8>, contained: u64) -> Self {
+        let len = payload.len() as u64;
+        let meta = if contained == 0 {
+            id.into_meta(len)
+        } else {
+            RecordMeta::new_container(id.source_id, id.type_id, len, contained)
+        };
+        Self {
+            meta,
+            payload,
+            parents: Vec::new(),
+        }
+    }
+
+    /// Length is that of the payload, i.e. excluding any encryption overhead if decrypted.
+    #[must_use]
+    pub const fn meta(&self) -> &RecordMeta {
+        &self.meta
+    }
+
+    #[must_use]
+    pub fn id(&self) -> RecordId {
+        self.meta.into()
+    }
+
+    #[must_use]
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    #[must_use]
+    pub fn into_payload(self) -> Vec<u8> {
+        self.payload
+    }
+
+    /// Containers enclosing the record when read, innermost first (empty if constructed).
+    #[must_use]
+    pub fn parents(&self) -> &[RecordId] {
+        &self.parents
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct RecordId {
-    pub(crate) source_id: u16,
-    pub(crate) type_id: u16,
+    pub(crate) source_id: u64,
+    pub(crate) type_id: u64,
 }
 
 impl RecordId {
     #[must_use] 
-    pub const fn new(source_id: u16, type_id: u16) -> Self {
+    pub const fn new(source_id: u64, type_id: u64) -> Self {
         Self { source_id, type_id }
     }
 
     #[must_use] 
     pub fn new_eos() -> Self {
         Self {
-            source_id: RECORD_EOS,
+            source_id: RECORD_EOS as u64,
             type_id: 0,
         }
     }
 
     #[must_use] 
     pub const fn is_eos(&self) -> bool {
-        self.source_id == RECORD_EOS
+        self.source_id == RECORD_EOS as u64
     }
 
     #[must_use]
-    pub const fn source_id(&self) -> u16 {
+    pub const fn source_id(&self) -> u64 {
         self.source_id
     }
 
     #[must_use]
-    pub const fn type_id(&self) -> u16 {
-        self.type_id & !TYPE_CONTAINER_MASK
+    pub const fn type_id(&self) -> u64 {
+        self.type_id
     }
 
     #[must_use]
@@ -184,6 +481,12 @@ impl RecordId {
     }
 }
 
+impl Display for RecordId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#06x}:{:#06x}", self.source_id(), self.type_id())
+    }
+}
+
 impl From<RecordMeta> for RecordId {
     fn from(value: RecordMeta) -> Self {
         RecordId::new(value.source_id, value.type_id)
@@ -196,8 +499,8 @@ mod test {
     use std::fmt::Debug;
 
     const LEN: u64 = 64;
-    const SOURCE: u16 = 1;
-    const TYPE: u16 = 2;
+    const SOURCE: u64 = 1;
+    const TYPE: u64 = 2;
 
     fn assert_pair_eq<T: Debug + PartialEq>(first: T, second: T, expected: T) {
         assert_eq!(first, expected);
@@ -217,7 +520,7 @@ mod test {
 
     #[test]
     fn record_interop_container() {
-        const COUNT: u16 = 5;
+        const COUNT: u64 = 5;
         let record_meta = RecordMeta::new_container(SOURCE, TYPE, LEN, COUNT);
         let record_id = RecordId::from(record_meta);
 
@@ -227,14 +530,28 @@ mod test {
         assert_pair_eq(record_meta.type_id(), record_id.type_id(), TYPE);
     }
 
+    #[test]
+    fn owned_container_empty() {
+        let record_id = RecordId::new(SOURCE, TYPE);
+        let record = OwnedRecord::new_container(record_id, vec![0; 3], 0);
+        assert_eq!(record.meta(), &record_id.into_meta(3));
+
+        let record = OwnedRecord::new_container(record_id, vec![0; 3], 2);
+        assert_eq!(record.meta().contained(), Some(2));
+    }
+
     #[test]
     fn record_interop_eos() {
-        let record_meta = RecordMeta::new(RECORD_EOS, TYPE, LEN);
+        let record_meta = RecordMeta::new(RECORD_EOS.into(), TYPE, LEN);
         let record_id = RecordId::from(record_meta);
 
         assert_eq!(record_meta, record_id.into_meta(LEN));
         assert_pair_eq(record_meta.is_eos(), record_id.is_eos(), true);
-        assert_pair_eq(record_meta.source_id(), record_id.source_id(), RECORD_EOS);
+        assert_pair_eq(
+            record_meta.source_id(),
+            record_id.source_id(),
+            RECORD_EOS.into(),
+        );
         assert_pair_eq(record_meta.type_id(), record_id.type_id(), TYPE);
     }
 }
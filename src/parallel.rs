@@ -0,0 +1,579 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io::Write,
+    panic::AssertUnwindSafe,
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+    thread::JoinHandle,
+};
+
+use crate::{
+    OwnedRecord, RecordId,
+    codec::RawSerialiser,
+    error::{IoError, ParserError},
+    writer::{HeaderInit, MsrfWriter},
+};
+
+/// Applied by the workers to each encoded payload before it is written (e.g. compression or
+/// a checksum), see [`ParallelOptions::transform`].
+pub type Transform = Arc<dyn Fn(RecordId, Vec<u8>) -> std::io::Result<Vec<u8>> + Send + Sync>;
+
+type Encode = Box<dyn FnOnce(&mut Vec<u8>) -> std::io::Result<()> + Send>;
+
+/// Options of a [`ParallelWriter`].
+#[derive(Clone)]
+pub struct ParallelOptions {
+    pub(crate) threads: usize,
+    pub(crate) max_in_flight: usize,
+    pub(crate) transform: Option<Transform>,
+}
+
+impl ParallelOptions {
+    /// Defaults to a worker per available core, with 4 records in flight per worker.
+    #[must_use]
+    pub fn new() -> Self {
+        let threads = std::thread::available_parallelism().map_or(1, usize::from);
+        Self {
+            threads,
+            max_in_flight: 4 * threads,
+            transform: None,
+        }
+    }
+
+    #[must_use]
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Records submitted but not yet written, beyond which submitting blocks.
+    ///
+    /// A container and its children are admitted together, even if they alone exceed the limit.
+    #[must_use]
+    pub fn max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight = max_in_flight.max(1);
+        self
+    }
+
+    #[must_use]
+    pub fn transform(
+        mut self,
+        transform: impl Fn(RecordId, Vec<u8>) -> std::io::Result<Vec<u8>> + Send + Sync + 'static,
+    ) -> Self {
+        self.transform = Some(Arc::new(transform));
+        self
+    }
+}
+
+impl Default for ParallelOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for ParallelOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ParallelOptions")
+            .field("threads", &self.threads)
+            .field("max_in_flight", &self.max_in_flight)
+            .field("transform", &self.transform.is_some())
+            .finish()
+    }
+}
+
+struct Job {
+    seq: u64,
+    id: RecordId,
+    contained: u64,
+    encode: Encode,
+}
+
+struct Encoded {
+    id: RecordId,
+    contained: u64,
+    payload: std::io::Result<Vec<u8>>,
+}
+
+#[derive(Default)]
+struct State {
+    // Sequence numbers, assigned in submission order
+    next_seq: u64,
+    next_emit: u64,
+    in_flight: usize,
+    jobs: VecDeque<Job>,
+    encoded: BTreeMap<u64, Encoded>,
+    is_closed: bool,
+    error: Option<IoError<ParserError>>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    // Signalled when a job is queued or the writer closed
+    jobs: Condvar,
+    // Signalled when a payload is encoded or the writer closed
+    encoded: Condvar,
+    // Signalled when a record is written (or discarded after an error)
+    space: Condvar,
+    options: ParallelOptions,
+}
+
+impl Shared {
+    // A panicking job is caught by its worker, so the state remains consistent
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn submit(&self, jobs: Vec<(RecordId, u64, Encode)>) -> Result<(), IoError<ParserError>> {
+        if jobs.iter().any(|(id, ..)| id.is_eos()) {
+            return Err(IoError::Parser(ParserError::UnexpectedEos));
+        }
+
+        let max = self.options.max_in_flight;
+        let mut state = self.lock();
+        while state.error.is_none()
+            && !state.is_closed
+            && state.in_flight > 0
+            && state.in_flight + jobs.len() > max
+        {
+            state = self
+                .space
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        if state.error.is_some() || state.is_closed {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "parallel writer failed or finished",
+            )
+            .into());
+        }
+
+        state.in_flight += jobs.len();
+        for (id, contained, encode) in jobs {
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.jobs.push_back(Job {
+                seq,
+                id,
+                contained,
+                encode,
+            });
+        }
+        drop(state);
+        self.jobs.notify_all();
+        Ok(())
+    }
+
+    fn close(&self) {
+        self.lock().is_closed = true;
+        self.jobs.notify_all();
+        self.encoded.notify_all();
+        self.space.notify_all();
+    }
+
+    fn work(&self) {
+        loop {
+            let mut state = self.lock();
+            let job = loop {
+                if let Some(job) = state.jobs.pop_front() {
+                    break job;
+                }
+                if state.is_closed {
+                    return;
+                }
+                state = self
+                    .jobs
+                    .wait(state)
+                    .unwrap_or_else(PoisonError::into_inner);
+            };
+            drop(state);
+
+            let payload = self.encode(job.id, job.encode);
+            let mut state = self.lock();
+            state.encoded.insert(
+                job.seq,
+                Encoded {
+                    id: job.id,
+                    contained: job.contained,
+                    payload,
+                },
+            );
+            let is_next = job.seq == state.next_emit;
+            drop(state);
+            if is_next {
+                self.encoded.notify_all();
+            }
+        }
+    }
+
+    fn encode(&self, id: RecordId, encode: Encode) -> std::io::Result<Vec<u8>> {
+        let res = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut payload = Vec::new();
+            encode(&mut payload)?;
+            match &self.options.transform {
+                Some(transform) => transform(id, payload),
+                None => Ok(payload),
+            }
+        }));
+        res.unwrap_or_else(|_| Err(std::io::Error::other("payload encoding panicked")))
+    }
+
+    // Writes encoded payloads in submission order, discarding those following an error
+    fn emit<S: RawSerialiser, W: Write>(&self, wtr: &mut MsrfWriter<S, W, HeaderInit>) {
+        loop {
+            let mut state = self.lock();
+            let encoded = loop {
+                let seq = state.next_emit;
+                if let Some(encoded) = state.encoded.remove(&seq) {
+                    break encoded;
+                }
+                if state.is_closed && seq == state.next_seq {
+                    return;
+                }
+                state = self
+                    .encoded
+                    .wait(state)
+                    .unwrap_or_else(PoisonError::into_inner);
+            };
+            let is_failed = state.error.is_some();
+            drop(state);
+
+            let res = if is_failed {
+                Ok(())
+            } else {
+                encoded.payload.map_err(IoError::from).and_then(|payload| {
+                    let record = OwnedRecord::new_container(encoded.id, payload, encoded.contained);
+                    wtr.write_owned(&record)
+                })
+            };
+
+            let mut state = self.lock();
+            state.next_emit += 1;
+            state.in_flight -= 1;
+            if let Err(e) = res {
+                state.error = Some(e);
+            }
+            drop(state);
+            self.space.notify_all();
+        }
+    }
+}
+
+/// Submits records to a [`ParallelWriter`] from any thread.
+#[derive(Clone)]
+pub struct Submitter {
+    shared: Arc<Shared>,
+}
+
+impl Submitter {
+    /// Queues a record whose payload is serialised by `f` on a worker, see
+    /// [`MsrfWriter::write_record_fn`].
+    ///
+    /// Blocks while [`ParallelOptions::max_in_flight`] records are in flight, and fails once the
+    /// writer has failed (see [`ParallelWriter::finish`]).
+    pub fn submit(
+        &self,
+        id: RecordId,
+        f: impl FnOnce(&mut Vec<u8>) -> std::io::Result<()> + Send + 'static,
+    ) -> Result<(), IoError<ParserError>> {
+        self.shared.submit(vec![(id, 0, Box::new(f))])
+    }
+
+    /// Queues a container and its children, which are written consecutively regardless of
+    /// records submitted concurrently. Without children, the container is written as a plain
+    /// record.
+    pub fn submit_container<F>(
+        &self,
+        id: RecordId,
+        f: impl FnOnce(&mut Vec<u8>) -> std::io::Result<()> + Send + 'static,
+        children: impl IntoIterator<Item = (RecordId, F)>,
+    ) -> Result<(), IoError<ParserError>>
+    where
+        F: FnOnce(&mut Vec<u8>) -> std::io::Result<()> + Send + 'static,
+    {
+        let mut jobs: Vec<(RecordId, u64, Encode)> = vec![(id, 0, Box::new(f))];
+        jobs.extend(
+            children
+                .into_iter()
+                .map(|(id, f)| (id, 0, Box::new(f) as Encode)),
+        );
+        jobs[0].1 = jobs.len() as u64 - 1;
+        self.shared.submit(jobs)
+    }
+}
+
+impl std::fmt::Debug for Submitter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Submitter")
+            .field("options", &self.shared.options)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Front-end to a [`MsrfWriter`] encoding payloads on a pool of workers, and writing them in
+/// submission order.
+///
+/// Dropping it without calling [`ParallelWriter::finish`] writes the records already submitted,
+/// then drops the writer (see [`MsrfWriter`]).
+pub struct ParallelWriter<S: RawSerialiser, W: Write> {
+    submitter: Submitter,
+    workers: Vec<JoinHandle<()>>,
+    emitter: Option<JoinHandle<MsrfWriter<S, W, HeaderInit>>>,
+}
+
+impl<S, W> ParallelWriter<S, W>
+where
+    S: RawSerialiser + Send + 'static,
+    W: Write + Send + 'static,
+{
+    pub fn new(mut wtr: MsrfWriter<S, W, HeaderInit>, options: ParallelOptions) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State::default()),
+            jobs: Condvar::new(),
+            encoded: Condvar::new(),
+            space: Condvar::new(),
+            options,
+        });
+
+        let workers = (0..shared.options.threads)
+            .map(|_| {
+                let shared = shared.clone();
+                std::thread::spawn(move || shared.work())
+            })
+            .collect();
+        let emitter = {
+            let shared = shared.clone();
+            std::thread::spawn(move || {
+                shared.emit(&mut wtr);
+                wtr
+            })
+        };
+
+        Self {
+            submitter: Submitter { shared },
+            workers,
+            emitter: Some(emitter),
+        }
+    }
+
+    pub fn submitter(&self) -> Submitter {
+        self.submitter.clone()
+    }
+
+    /// See [`Submitter::submit`].
+    pub fn submit(
+        &self,
+        id: RecordId,
+        f: impl FnOnce(&mut Vec<u8>) -> std::io::Result<()> + Send + 'static,
+    ) -> Result<(), IoError<ParserError>> {
+        self.submitter.submit(id, f)
+    }
+
+    /// See [`Submitter::submit_container`].
+    pub fn submit_container<F>(
+        &self,
+        id: RecordId,
+        f: impl FnOnce(&mut Vec<u8>) -> std::io::Result<()> + Send + 'static,
+        children: impl IntoIterator<Item = (RecordId, F)>,
+    ) -> Result<(), IoError<ParserError>>
+    where
+        F: FnOnce(&mut Vec<u8>) -> std::io::Result<()> + Send + 'static,
+    {
+        self.submitter.submit_container(id, f, children)
+    }
+
+    /// Writes the records submitted so far (further submissions fail), then finishes the stream.
+    ///
+    /// Returns the first error encoding or writing a record, in which case the stream is left
+    /// unfinished.
+    pub fn finish(mut self) -> Result<W, IoError<ParserError>> {
+        let wtr = self.join();
+        match self.submitter.shared.lock().error.take() {
+            Some(e) => {
+                drop(wtr.into_inner_unfinished());
+                Err(e)
+            }
+            None => wtr.finish(),
+        }
+    }
+
+    fn join(&mut self) -> MsrfWriter<S, W, HeaderInit> {
+        self.submitter.shared.close();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+        // SAFETY: Only taken by `finish` or drop
+        let emitter = self.emitter.take().unwrap();
+        emitter
+            .join()
+            .unwrap_or_else(|e| std::panic::resume_unwind(e))
+    }
+}
+
+impl<S: RawSerialiser, W: Write> Drop for ParallelWriter<S, W> {
+    fn drop(&mut self) {
+        self.submitter.shared.close();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+        // A stream missing a failed record is left unfinished, as by `finish`
+        if let Some(Ok(wtr)) = self.emitter.take().map(JoinHandle::join)
+            && self.submitter.shared.lock().error.is_some()
+        {
+            drop(wtr.into_inner_unfinished());
+        }
+    }
+}
+
+#[cfg(all(test, feature = "reader"))]
+mod test {
+    use std::{io::Cursor, time::Duration};
+
+    use super::*;
+    use crate::{error::ErrorKind, reader::MsrfReaderBuilder, writer::MsrfWriterBuilder};
+
+    fn new_writer(
+        options: ParallelOptions,
+    ) -> ParallelWriter<crate::codec::AnySerialiser, Vec<u8>> {
+        let wtr = MsrfWriterBuilder::new()
+            .build(Vec::new())
+            .expect("unsupported version")
+            .initialise()
+            .expect("failed to initialise");
+        ParallelWriter::new(wtr, options)
+    }
+
+    fn read_records(data: Vec<u8>) -> Vec<OwnedRecord> {
+        MsrfReaderBuilder::new()
+            .build(Cursor::new(data))
+            .expect("failed to build")
+            .into_records()
+            .collect::<Result<_, _>>()
+            .expect("failed read")
+    }
+
+    #[test]
+    fn parallel_ordered() {
+        let wtr = new_writer(ParallelOptions::new().threads(4).max_in_flight(8));
+        for value in 0..32_u8 {
+            // Earlier records take longer to encode
+            wtr.submit(RecordId::new(1, 1), move |w| {
+                std::thread::sleep(Duration::from_millis(u64::from(32 - value) / 4));
+                w.write_all(&[value])
+            })
+            .expect("failed submit");
+        }
+        let children = (0..3_u8).map(|value| {
+            let f = move |w: &mut Vec<u8>| w.write_all(&[value]);
+            (RecordId::new(1, 2), f)
+        });
+        wtr.submit_container(RecordId::new(1, 3), |_| Ok(()), children)
+            .expect("failed submit");
+
+        let records = read_records(wtr.finish().expect("failed finish"));
+        let payloads: Vec<_> = records[..32].iter().map(OwnedRecord::payload).collect();
+        assert_eq!(
+            payloads,
+            (0..32_u8).map(|value| [value]).collect::<Vec<_>>()
+        );
+        assert_eq!(records[32].meta().contained(), Some(3));
+        let container = [RecordId::new(1, 3)];
+        assert!(
+            records[33..]
+                .iter()
+                .all(|record| record.parents() == container)
+        );
+    }
+
+    #[test]
+    fn parallel_submitters() {
+        let options = ParallelOptions::new()
+            .threads(2)
+            .max_in_flight(4)
+            .transform(|_, mut payload| {
+                payload.push(0xff);
+                Ok(payload)
+            });
+        let wtr = new_writer(options);
+        std::thread::scope(|scope| {
+            for thread in 0..4_u8 {
+                let submitter = wtr.submitter();
+                scope.spawn(move || {
+                    for _ in 0..16 {
+                        let children = [(RecordId::new(1, 2), move |w: &mut Vec<u8>| {
+                            w.write_all(&[thread])
+                        })];
+                        submitter
+                            .submit_container(RecordId::new(1, 3), |_| Ok(()), children)
+                            .expect("failed submit");
+                    }
+                });
+            }
+        });
+
+        let records = read_records(wtr.finish().expect("failed finish"));
+        assert_eq!(records.len(), 4 * 16 * 2);
+        for pair in records.chunks(2) {
+            assert_eq!(pair[0].meta().contained(), Some(1));
+            assert_eq!(pair[1].parents(), [pair[0].id()]);
+            assert_eq!(pair[1].payload()[1], 0xff);
+        }
+    }
+
+    #[test]
+    fn parallel_error() {
+        let wtr = new_writer(ParallelOptions::new().threads(2));
+        wtr.submit(RecordId::new(1, 1), |w| w.write_all(&[0]))
+            .expect("failed submit");
+        wtr.submit(RecordId::new(1, 1), |_| {
+            Err(std::io::Error::other("failed"))
+        })
+        .expect("failed submit");
+        // May be rejected, depending on whether the error has been written
+        let _ = wtr.submit(RecordId::new(1, 1), |w| w.write_all(&[2]));
+
+        let err = wtr.finish().expect_err("succeeded finish");
+        assert_eq!(err.kind(), ErrorKind::Io);
+        assert_eq!(err.to_string(), "failed");
+    }
+
+    #[test]
+    fn parallel_error_drop() {
+        #[derive(Clone, Default)]
+        struct Shared(Arc<Mutex<Vec<u8>>>);
+
+        impl Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let data = Shared::default();
+        let wtr = MsrfWriterBuilder::new()
+            .build(data.clone())
+            .expect("unsupported version")
+            .initialise()
+            .expect("failed to initialise");
+        let wtr = ParallelWriter::new(wtr, ParallelOptions::new().threads(2));
+        wtr.submit(RecordId::new(1, 1), |w| w.write_all(&[0]))
+            .expect("failed submit");
+        wtr.submit(RecordId::new(1, 1), |_| {
+            Err(std::io::Error::other("failed"))
+        })
+        .expect("failed submit");
+        drop(wtr);
+
+        // Left without EoS, as by `finish`
+        let data = data.0.lock().unwrap().clone();
+        let err = MsrfReaderBuilder::new()
+            .build(Cursor::new(data))
+            .expect("failed to build")
+            .into_records()
+            .collect::<Result<Vec<_>, _>>()
+            .expect_err("succeeded read");
+        assert_eq!(err.kind(), ErrorKind::Io);
+    }
+}
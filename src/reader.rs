@@ -1,17 +1,34 @@
-use std::io::Read;
+use std::io::{BufRead, Read, Seek, SeekFrom};
 
+use sha2::Digest;
+
+#[cfg(feature = "encryption")]
+use crate::encryption::{self, Decryption};
 use crate::{
-    CURRENT_VERSION, RecordId, RecordMeta,
-    codec::{self, AnyDeserialiser, RawDeserialiser, UnknownSerdes, constants::HEADER_LEN},
-    error::{IoError, ParserError},
-    io::RecordChunk,
+    CURRENT_VERSION, Features, Header, OwnedRecord, RecordId, RecordMeta, RecordSpan,
+    checkpoint::Checkpoint,
+    codec::{
+        self, AnyDeserialiser, DesOptions, RawDeserialiser, UnknownSerdes, constants::HEADER_LEN,
+    },
+    error::{BuildError, ErrorKind, IoError, ParserError, Position, PositionedError, ReadError},
+    integrity::{DIGEST_RECORD, IntegrityReader},
+    io::{
+        self, CountingReader, DigestReader, Follow, FollowOptions, PayloadState, ReadAhead,
+        ReadExt, RecordChunk,
+    },
+    trailer::{StreamDigest, Trailer},
 };
 
+// Payload bytes reserved ahead of those read when the length is unbounded by `DesOptions`
+const UNBOUNDED_READ_STEP: usize = 1 << 16;
+
 pub type DeserialiseResult<T> = Result<(T, usize), Result<usize, ParserError>>;
 
 #[derive(Debug, Default, Clone)]
 pub struct MsrfReaderBuilder {
     version: Option<u16>,
+    concatenated: bool,
+    options: DesOptions,
 }
 
 impl MsrfReaderBuilder {
@@ -20,60 +37,271 @@ impl MsrfReaderBuilder {
         MsrfReaderBuilder::default()
     }
 
+    /// Requires the header to declare `version`, rather than accepting any supported version.
     #[must_use]
     pub fn version(mut self, version: u16) -> MsrfReaderBuilder {
         self.version = Some(version);
         self
     }
 
-    // TODO: Error
-    pub fn build<R: Read>(self, wtr: R) -> Option<MsrfReader<AnyDeserialiser, R>> {
-        let version = self.version.unwrap_or(CURRENT_VERSION);
-        let des = AnyDeserialiser::new_default(version)?;
-        Some(MsrfReader::new(wtr, des))
+    /// Continue past EoS into the next stream if one immediately follows (e.g. files joined
+    /// with `cat`). See [`MsrfReader::read_event`].
+    #[must_use]
+    pub fn concatenated(mut self, concatenated: bool) -> MsrfReaderBuilder {
+        self.concatenated = concatenated;
+        self
+    }
+
+    /// Limits enforced while reading, see [`DesOptions`].
+    #[must_use]
+    pub fn options(mut self, options: DesOptions) -> MsrfReaderBuilder {
+        self.options = options;
+        self
+    }
+
+    /// Reads the header, selecting a deserialiser for the version it declares.
+    pub fn build<R: Read>(self, wtr: R) -> Result<MsrfReader<AnyDeserialiser, R>, BuildError> {
+        if let Some(version) = self.version
+            && version > CURRENT_VERSION
+        {
+            return Err(BuildError::Unsupported(version));
+        }
+
+        let expected = self.version;
+        let rdr = self.build_with_unknown(wtr).initialise()?;
+        match expected {
+            Some(expected) if expected != rdr.version() => Err(BuildError::Version {
+                expected,
+                found: rdr.version(),
+            }),
+            _ => Ok(rdr),
+        }
     }
 
     pub fn build_with_unknown<R: Read>(self, wtr: R) -> MsrfReader<UnknownSerdes, R> {
-        MsrfReader::new_unknown(wtr)
+        let mut rdr = MsrfReader::new_unknown(wtr).with_concatenated(self.concatenated);
+        rdr.options = self.options;
+        rdr
     }
 
+    /// The container depth and record count limits of [`MsrfReaderBuilder::options`] are
+    /// enforced by the reader, while any remaining limits are left to `des`.
     pub fn build_with<R: Read, D: RawDeserialiser>(self, wtr: R, des: D) -> MsrfReader<D, R> {
-        MsrfReader::new(wtr, des)
+        let mut rdr = MsrfReader::new(wtr, des).with_concatenated(self.concatenated);
+        rdr.options = self.options;
+        rdr
+    }
+
+    /// Resumes reading with the limits of [`MsrfReaderBuilder::options`], see
+    /// [`MsrfReader::resume`].
+    pub fn resume<R: Read + Seek>(
+        self,
+        rdr: R,
+        checkpoint: &Checkpoint,
+    ) -> Result<MsrfReader<AnyDeserialiser, R>, ReadError> {
+        MsrfReader::resume_with(rdr, checkpoint, self.options)
+    }
+}
+
+/// Produced by [`MsrfReader::read_event`].
+pub enum ReadEvent<'a, R: Read> {
+    Record(RecordId, RecordChunk<'a, R>),
+    /// The previous stream ended and another began (concatenated mode only).
+    Member(Header),
+}
+
+/// Produced by [`MsrfReader::into_records`].
+pub struct IntoRecords<D, R> {
+    rdr: MsrfReader<D, R>,
+    is_done: bool,
+}
+
+impl<D, R> IntoRecords<D, R> {
+    /// Returns the reader, e.g. to inspect its trailer once exhausted.
+    pub fn into_inner(self) -> MsrfReader<D, R> {
+        self.rdr
     }
 }
 
+impl<D: RawDeserialiser, R: Read> Iterator for IntoRecords<D, R> {
+    type Item = Result<OwnedRecord, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.is_done {
+            return None;
+        }
+
+        match self.rdr.read_owned() {
+            Ok(record) => Some(Ok(record)),
+            Err(e) => {
+                self.is_done = true;
+                (e.kind() != ErrorKind::IsEos || !self.rdr.is_finished).then_some(Err(e))
+            }
+        }
+    }
+}
+
+impl<D: RawDeserialiser, R: Read> std::iter::FusedIterator for IntoRecords<D, R> {}
+
 // TODO: Builder
 // TODO: Config
 pub struct MsrfReader<D, R> {
     is_finished: bool,
-    rdr: R,
+    concatenated: bool,
+    following: bool,
+    member: usize,
+    rdr: ReadAhead<R>,
     des: D,
-    depth: Vec<(u16, RecordId)>,
+    depth: Vec<(u64, RecordId)>,
+    position: u64,
+    header: Option<Header>,
+    records: u64,
+    record_id: Option<RecordId>,
+    record_span: Option<RecordSpan>,
+    record_offset: u64,
+    header_offset: u64,
+    pending_guard: bool,
+    options: DesOptions,
+    digest: Option<StreamDigest>,
+    stats: Option<Trailer>,
+    trailer: Option<Trailer>,
+    integrity: Option<IntegrityReader>,
+    #[cfg(feature = "encryption")]
+    decryption: Decryption,
+    payload: PayloadState,
+    poisoned: bool,
+    buf: Vec<u8>,
+    // Bytes of a meta straddling the read-ahead buffer, kept if the input runs dry
+    partial_meta: Vec<u8>,
+}
+
+impl<D, R> MsrfReader<D, R> {
+    #[must_use]
+    pub fn with_concatenated(mut self, concatenated: bool) -> Self {
+        self.concatenated = concatenated;
+        self
+    }
+
+    /// Header of the current stream, unless this reader was constructed after it was read.
+    pub fn header(&self) -> Option<&Header> {
+        self.header.as_ref()
+    }
+
+    /// Polls for more data on EOF instead of failing, until EoS is read or `options` times out.
+    ///
+    /// Partially written records are resumed once the remainder becomes available. Timing out
+    /// within a meta consumes none of it, so the read may be retried, whereas reads of a
+    /// [`RecordChunk`] may be retried from where they timed out. As the input may still be
+    /// growing, data following EoS is never treated as trailing data.
+    pub fn follow(self, options: FollowOptions) -> MsrfReader<D, Follow<R>>
+    where
+        R: Read,
+    {
+        MsrfReader {
+            is_finished: self.is_finished,
+            concatenated: self.concatenated,
+            following: true,
+            member: self.member,
+            rdr: self.rdr.map(|rdr| Follow::new(rdr, options)),
+            des: self.des,
+            depth: self.depth,
+            position: self.position,
+            header: self.header,
+            records: self.records,
+            record_id: self.record_id,
+            record_span: self.record_span,
+            record_offset: self.record_offset,
+            header_offset: self.header_offset,
+            pending_guard: self.pending_guard,
+            options: self.options,
+            digest: self.digest,
+            stats: self.stats,
+            trailer: self.trailer,
+            integrity: self.integrity,
+            #[cfg(feature = "encryption")]
+            decryption: self.decryption,
+            payload: self.payload,
+            poisoned: self.poisoned,
+            buf: self.buf,
+            partial_meta: self.partial_meta,
+        }
+    }
 }
 
 impl<R: Read> MsrfReader<UnknownSerdes, R> {
     pub fn new_unknown(rdr: R) -> MsrfReader<UnknownSerdes, R> {
         MsrfReader {
             is_finished: false,
-            rdr,
+            concatenated: false,
+            following: false,
+            member: 0,
+            rdr: ReadAhead::new(rdr),
             des: UnknownSerdes,
             depth: Vec::new(),
+            position: 0,
+            header: None,
+            records: 0,
+            record_id: None,
+            record_span: None,
+            record_offset: 0,
+            header_offset: 0,
+            pending_guard: false,
+            options: DesOptions::default(),
+            digest: None,
+            stats: None,
+            trailer: None,
+            integrity: None,
+            #[cfg(feature = "encryption")]
+            decryption: Decryption::default(),
+            payload: PayloadState::default(),
+            poisoned: false,
+            buf: Vec::new(),
+            partial_meta: Vec::new(),
         }
     }
 
-    pub fn initialise(mut self) -> Result<MsrfReader<AnyDeserialiser, R>, IoError<ParserError>> {
+    pub fn initialise(mut self) -> Result<MsrfReader<AnyDeserialiser, R>, ReadError> {
+        let error = |e: IoError<ParserError>| ReadError::new(e, Position::new(self.position, 0));
         let mut buf = [0; HEADER_LEN];
-        self.rdr.read_exact(&mut buf)?;
+        self.rdr.read_exact(&mut buf).map_err(|e| error(e.into()))?;
 
-        let header = codec::read_header(&buf)?;
-        let des = AnyDeserialiser::new_default(header.version)
-            .ok_or(ParserError::Unsupported(header.version))?;
+        let mut header = codec::read_header(&buf).map_err(|e| error(e.into()))?;
+        let des = AnyDeserialiser::new(header.version, self.options.clone())
+            .ok_or_else(|| error(ParserError::Unsupported(header.version).into()))?;
+        let mut digest = StreamDigest::new_with_prefix(buf);
+        let rdr = DigestReader::new(&mut self.rdr, Some(&mut digest));
+        let extension_len =
+            codec::read_header_extension(rdr, &mut header, &self.options).map_err(error)?;
+        let trailer = header.declares(Features::TRAILER);
+        let integrity = header.declares(Features::INTEGRITY);
 
         Ok(MsrfReader {
             is_finished: false,
+            concatenated: self.concatenated,
+            following: self.following,
+            member: self.member,
             rdr: self.rdr,
             des,
             depth: Vec::new(),
+            position: self.position + HEADER_LEN as u64 + extension_len,
+            header: Some(header),
+            records: 0,
+            record_id: None,
+            record_span: None,
+            record_offset: 0,
+            header_offset: self.position,
+            pending_guard: false,
+            options: self.options,
+            digest: (trailer || integrity).then_some(digest),
+            stats: trailer.then(Trailer::default),
+            trailer: None,
+            integrity: integrity.then(IntegrityReader::default),
+            #[cfg(feature = "encryption")]
+            decryption: Decryption::default(),
+            payload: PayloadState::default(),
+            poisoned: false,
+            buf: Vec::new(),
+            partial_meta: Vec::new(),
         })
     }
 }
@@ -82,9 +310,31 @@ impl<D: RawDeserialiser, R: Read> MsrfReader<D, R> {
     pub fn new(rdr: R, des: D) -> MsrfReader<D, R> {
         MsrfReader {
             is_finished: false,
-            rdr,
+            concatenated: false,
+            following: false,
+            member: 0,
+            rdr: ReadAhead::new(rdr),
             des,
             depth: Vec::new(),
+            position: 0,
+            header: None,
+            records: 0,
+            record_id: None,
+            record_span: None,
+            record_offset: 0,
+            header_offset: 0,
+            pending_guard: false,
+            options: DesOptions::default(),
+            digest: None,
+            stats: None,
+            trailer: None,
+            integrity: None,
+            #[cfg(feature = "encryption")]
+            decryption: Decryption::default(),
+            payload: PayloadState::default(),
+            poisoned: false,
+            buf: Vec::new(),
+            partial_meta: Vec::new(),
         }
     }
 
@@ -105,24 +355,428 @@ impl<D: RawDeserialiser, R: Read> MsrfReader<D, R> {
         }
     }
 
-    // TODO: Return Err(ParserError::IsEos) on EoS byte rather than Some(None)?
-    pub fn read_record(
-        &mut self,
-    ) -> Result<(RecordId, RecordChunk<'_, R>), IoError<ParserError>> {
+    fn error(&self, offset: u64, error: impl Into<IoError<ParserError>>) -> ReadError {
+        ReadError::new(
+            error,
+            Position::new(offset, self.records).with_path(self.parents()),
+        )
+    }
+
+    /// Attaches the position of the most recently read record to `error`, e.g. one raised
+    /// while decoding its payload.
+    ///
+    /// The attached path is that of [`MsrfReader::parents`], which includes the record itself
+    /// if it is a non-empty container.
+    pub fn annotate<E>(&self, error: impl Into<IoError<E>>) -> PositionedError<E> {
+        let mut position = Position::new(self.record_offset, self.records.saturating_sub(1))
+            .with_path(self.parents());
+        if let Some(id) = self.record_id {
+            position = position.with_id(id);
+        }
+        PositionedError::new(error, position)
+    }
+
+    // Skips any unread payload of the previous record, then decodes the next meta. Errors leave
+    // the reader poisoned unless they occur between records, i.e. it is safe to retry.
+    pub(crate) fn next_meta(&mut self) -> Result<RecordMeta, ReadError> {
+        if self.poisoned {
+            return Err(self.error(self.position, ParserError::Poisoned));
+        }
+        self.skip_payload()?;
+
+        self.poisoned = true;
+        let res = self.read_next_meta();
+        if res.is_ok() || res.as_ref().is_err_and(|e| e.kind() == ErrorKind::IsEos) {
+            self.poisoned = false;
+        }
+        res
+    }
+
+    // Returns the error which stopped the previous payload being skipped when dropped (once),
+    // resuming the skip on the following call
+    fn skip_payload(&mut self) -> Result<(), ReadError> {
+        if let Some(e) = self.payload.error.take() {
+            return Err(self.annotate(e));
+        }
+        let rdr = DigestReader::new(&mut self.rdr, self.digest.as_mut());
+        io::skip(rdr, &mut self.payload.remaining).map_err(|e| self.annotate(e))
+    }
+
+    // Consumes the guard trailing the previous record (if any) and decodes the next meta,
+    // verifying and skipping digest records
+    fn read_next_meta(&mut self) -> Result<RecordMeta, ReadError> {
         if self.is_finished {
-            return Err(IoError::Parser(ParserError::IsEos));
+            return Err(self.error(self.position, ParserError::IsEos));
         }
 
-        let record = self.des.read_meta(&mut self.rdr)?;
+        let (offset, record, meta_len) = loop {
+            let (offset, record, meta_len) = self.read_meta()?;
+            if record.contained().is_none()
+                && RecordId::from(record) == DIGEST_RECORD
+                && self
+                    .header
+                    .as_ref()
+                    .is_some_and(|header| header.declares(Features::INTEGRITY))
+            {
+                self.read_digest(offset, &record)?;
+            } else {
+                break (offset, record, meta_len);
+            }
+        };
 
         if record.is_eos() {
             self.is_finished = true;
-            return Err(IoError::Parser(ParserError::IsEos));
+            self.record_id = None;
+            self.record_span = None;
+            // Without digests (undeclared or resumed) nothing was signed, failing if required
+            self.integrity
+                .clone()
+                .unwrap_or_default()
+                .finish(&self.options)
+                .map_err(|e| self.error(offset, e))?;
+            if self
+                .header
+                .as_ref()
+                .is_some_and(|header| header.declares(Features::TRAILER))
+            {
+                let digest = self.digest.take();
+                self.read_trailer(digest)?;
+            }
+            if self.options.strictness.is_some() && !self.concatenated && !self.following {
+                self.check_trailing()?;
+            }
+            return Err(self.error(self.record_offset, ParserError::IsEos));
+        }
+
+        let violation = |error: ParserError| {
+            let position = Position::new(offset, self.records)
+                .with_id(record.into())
+                .with_path(self.parents());
+            self.options.violation(ReadError::new(error, position))
+        };
+        if meta_len != self.des.encoded_meta_len(&record) as u64 {
+            violation(ParserError::NonMinimal(record.length))?;
+        }
+        if record.contained() == Some(0) {
+            violation(ParserError::EmptyContainer)?;
+        }
+
+        if self
+            .options
+            .max_records
+            .is_some_and(|max| self.records >= max)
+        {
+            return Err(self.error(offset, ParserError::Records(self.records + 1)));
+        }
+        if record.contained().is_some_and(|count| count > 0)
+            && self
+                .options
+                .max_depth
+                .is_some_and(|max| self.depth.len() >= max)
+        {
+            return Err(self.error(offset, ParserError::Depth(self.depth.len() + 1)));
         }
 
         self.update(&record);
-        let ref_rdr = RecordChunk::new(&mut self.rdr, record.length);
-        Ok((record.into(), ref_rdr))
+        if let Some(stats) = &mut self.stats {
+            stats.update(&record, self.depth.len());
+        }
+        if let Some(integrity) = &mut self.integrity {
+            integrity.pending = true;
+        }
+        self.records += 1;
+        self.record_id = Some(record.into());
+        self.record_span = Some(RecordSpan {
+            meta: offset,
+            payload: self.position,
+            end: self.position + record.length,
+        });
+        self.position += record.length;
+        self.pending_guard = true;
+        Ok(record)
+    }
+
+    fn read_meta(&mut self) -> Result<(u64, RecordMeta, u64), ReadError> {
+        if self.pending_guard {
+            let guard = DigestReader::new(&mut self.rdr, self.digest.as_mut()).read_chunk();
+            // Nothing has been consumed, so the read may be retried
+            let [guard] = guard.map_err(|e| {
+                self.poisoned = false;
+                self.annotate(e)
+            })?;
+            self.position += 1;
+            self.pending_guard = false;
+            if guard != 0 {
+                self.options
+                    .violation(self.annotate(ParserError::Guard(guard)))?;
+            }
+        }
+
+        let offset = self.position;
+        let (record, meta_len) = self.decode_meta();
+        self.position += meta_len;
+        let record = record.map_err(|e| {
+            self.poisoned = meta_len > 0;
+            self.error(offset, e)
+        })?;
+        self.record_offset = offset;
+        Ok((offset, record, meta_len))
+    }
+
+    // Decodes the meta from the read-ahead buffer in one pass if it holds the whole meta,
+    // otherwise accumulating it across reads, returning the number of bytes consumed
+    fn decode_meta(&mut self) -> (Result<RecordMeta, IoError<ParserError>>, u64) {
+        if self.partial_meta.is_empty() {
+            let buf = match self.rdr.fill_buf() {
+                Ok(buf) => buf,
+                Err(e) => return (Err(e.into()), 0),
+            };
+            let mut rdr = buf;
+            let record = self.des.read_meta(&mut rdr);
+            let consumed = buf.len() - rdr.len();
+            if !is_unexpected_eof(&record) {
+                if let Some(digest) = &mut self.digest {
+                    digest.update(&buf[..consumed]);
+                }
+                self.rdr.consume(consumed);
+                return (record, consumed as u64);
+            }
+        }
+
+        // Bytes accumulated so far are kept on failure (e.g. a followed input timing out), so
+        // nothing is consumed until the meta is whole and the read may be retried
+        loop {
+            let buf = match self.rdr.fill_buf() {
+                Ok(buf) => buf,
+                Err(e) => return (Err(e.into()), 0),
+            };
+            let start = self.partial_meta.len();
+            let is_eof = buf.is_empty();
+            self.partial_meta.extend_from_slice(buf);
+
+            let mut rdr = self.partial_meta.as_slice();
+            let record = self.des.read_meta(&mut rdr);
+            let consumed = self.partial_meta.len() - rdr.len();
+            if !is_unexpected_eof(&record) {
+                if let Some(digest) = &mut self.digest {
+                    digest.update(&self.partial_meta[..consumed]);
+                }
+                self.rdr.consume(consumed - start);
+                self.partial_meta.clear();
+                return (record, consumed as u64);
+            } else if is_eof {
+                return (record, 0);
+            }
+            let len = self.partial_meta.len() - start;
+            self.rdr.consume(len);
+        }
+    }
+
+    // Verifies a digest record against the stream read so far, unless resumed from a checkpoint
+    fn read_digest(&mut self, offset: u64, meta: &RecordMeta) -> Result<(), ReadError> {
+        if let Some(integrity) = &mut self.integrity
+            && let Some(digest) = &mut self.digest
+        {
+            let expected = digest.clone().finalize().into();
+            let payload =
+                RecordChunk::new(&mut self.rdr, meta.length, Some(digest), &mut self.payload);
+            let res = integrity.verify(meta, payload, expected, self.records, &self.options);
+            res.map_err(|e| self.error(offset, e))?;
+        } else {
+            let res = self.chunk(meta.length).drain();
+            res.map_err(|e| self.error(offset, e))?;
+        }
+        self.position += meta.length;
+        self.pending_guard = true;
+        Ok(())
+    }
+
+    // Reads the trailer following EoS, which must match the stream read. Without a digest (once
+    // resumed) too little of the stream was read to verify it, so it is only skipped
+    fn read_trailer(&mut self, digest: Option<StreamDigest>) -> Result<(), ReadError> {
+        let offset = self.position;
+        let mut rdr = CountingReader::new(&mut self.rdr);
+        let trailer = Trailer::read_from(&mut rdr);
+        self.position += rdr.count();
+        let trailer = trailer.map_err(|e| self.error(offset, e))?;
+        let Some(digest) = digest else {
+            return Ok(());
+        };
+
+        let mut expected = self.stats.take().unwrap_or_default();
+        expected.digest = digest.finalize().into();
+        if trailer != expected {
+            return Err(self.error(offset, ParserError::Trailer));
+        }
+        self.trailer = Some(trailer);
+        Ok(())
+    }
+
+    // Strict and lenient modes expect EoS to be the end of input
+    fn check_trailing(&mut self) -> Result<(), ReadError> {
+        let mut buf = [0; 1];
+        loop {
+            match self.rdr.read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(_) => break,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(self.error(self.position, e)),
+            }
+        }
+        self.options
+            .violation(self.error(self.position, ParserError::TrailingData))
+    }
+
+    pub(crate) fn chunk(&mut self, len: u64) -> RecordChunk<'_, R> {
+        RecordChunk::new(&mut self.rdr, len, self.digest.as_mut(), &mut self.payload)
+    }
+
+    // TODO: Return Err(ParserError::IsEos) on EoS byte rather than Some(None)?
+    pub fn read_record(&mut self) -> Result<(RecordId, RecordChunk<'_, R>), ReadError> {
+        let record = self.next_meta()?;
+        Ok((record.into(), self.payload(&record)?))
+    }
+
+    /// Reads the next record and its payload (decrypted if a key is available) in full.
+    pub fn read_owned(&mut self) -> Result<OwnedRecord, ReadError> {
+        // Containers are popped as their final child is read, so are collected beforehand
+        let parents = self.parents().collect();
+        let mut meta = self.next_meta()?;
+        let mut payload = Vec::new();
+        self.read_payload_into(&meta, &mut payload)?;
+        meta.length = payload.len() as u64;
+        Ok(OwnedRecord {
+            meta,
+            payload,
+            parents,
+        })
+    }
+
+    /// Iterates over the remaining records until EoS, ending after the first error.
+    pub fn into_records(self) -> IntoRecords<D, R> {
+        IntoRecords {
+            rdr: self,
+            is_done: false,
+        }
+    }
+
+    /// Reads the next record, replacing the contents of `buf` with its payload.
+    ///
+    /// The allocation of `buf` is reused, and only grown up front if the payload length is
+    /// bounded by [`DesOptions::max_record_len`] (otherwise growing as the payload is read).
+    pub fn read_record_into(&mut self, buf: &mut Vec<u8>) -> Result<RecordId, ReadError> {
+        buf.clear();
+        let meta = self.next_meta()?;
+        self.read_payload_into(&meta, buf)?;
+        Ok(meta.into())
+    }
+
+    /// Reads the next record into a buffer reused across calls, passing its payload to `f`.
+    pub fn read_record_with<T>(
+        &mut self,
+        f: impl FnOnce(RecordId, &[u8]) -> T,
+    ) -> Result<T, ReadError> {
+        let mut buf = std::mem::take(&mut self.buf);
+        let res = self.read_record_into(&mut buf).map(|id| f(id, &buf));
+        self.buf = buf;
+        res
+    }
+
+    // Appends the payload of `meta` to `buf`, decrypted if a key is available
+    fn read_payload_into(&mut self, meta: &RecordMeta, buf: &mut Vec<u8>) -> Result<(), ReadError> {
+        #[cfg(feature = "encryption")]
+        if let Some(key) = self.decryption.key(meta.source_id(), &self.options) {
+            self.decrypt(meta, &key)?;
+            buf.extend_from_slice(&self.decryption.plaintext);
+            return Ok(());
+        }
+
+        self.read_stream_into(meta.length, buf)
+    }
+
+    // Appends `len` bytes of the stream to `buf`, tracked as the current payload so a failed read
+    // is skipped by the next
+    fn read_stream_into(&mut self, len: u64, buf: &mut Vec<u8>) -> Result<(), ReadError> {
+        let step = if self.options.max_record_len.is_some() {
+            usize::MAX
+        } else {
+            UNBOUNDED_READ_STEP
+        };
+        self.payload = PayloadState {
+            remaining: len,
+            error: None,
+        };
+        let rdr = DigestReader::new(&mut self.rdr, self.digest.as_mut());
+        io::read_into(rdr, buf, &mut self.payload.remaining, step).map_err(|e| self.annotate(e))
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    pub(crate) fn payload(&mut self, meta: &RecordMeta) -> Result<RecordChunk<'_, R>, ReadError> {
+        Ok(self.chunk(meta.length))
+    }
+
+    // Encrypted payloads are read in full to be authenticated before any is returned
+    #[cfg(feature = "encryption")]
+    pub(crate) fn payload(&mut self, meta: &RecordMeta) -> Result<RecordChunk<'_, R>, ReadError> {
+        let Some(key) = self.decryption.key(meta.source_id(), &self.options) else {
+            return Ok(self.chunk(meta.length));
+        };
+
+        self.decrypt(meta, &key)?;
+        Ok(RecordChunk::buffered(&self.decryption.plaintext))
+    }
+
+    #[cfg(feature = "encryption")]
+    fn decrypt(&mut self, meta: &RecordMeta, key: &encryption::Key) -> Result<(), ReadError> {
+        let mut ciphertext = Vec::new();
+        self.read_stream_into(meta.length, &mut ciphertext)?;
+        // Counted once its meta was read
+        let index = self.records.saturating_sub(1);
+        self.decryption.plaintext =
+            encryption::decrypt(key, meta, index, &ciphertext).map_err(|e| self.annotate(e))?;
+        Ok(())
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.is_finished
+    }
+
+    /// Trailer of the current stream, once read and verified at EoS.
+    ///
+    /// Only streams declaring [`Features::TRAILER`] have a trailer. A reader resumed from a
+    /// [`Checkpoint`] cannot verify it, so skips it and returns `None`.
+    pub fn trailer(&self) -> Option<&Trailer> {
+        self.trailer.as_ref()
+    }
+
+    /// Index of the current stream within a concatenated input, starting from 0.
+    pub fn member(&self) -> usize {
+        self.member
+    }
+
+    /// Number of records read, excluding EoS.
+    pub fn records(&self) -> u64 {
+        self.records
+    }
+
+    /// Offsets of the most recently read record, or `None` if no record has been read since the
+    /// start of the stream or EoS.
+    pub fn record_span(&self) -> Option<RecordSpan> {
+        self.record_span
+    }
+
+    /// Absolute position of the reader, i.e. the total bytes read (including the header and
+    /// guards) plus the initial offset if resumed from a [`Checkpoint`].
+    ///
+    /// The payload of the most recently read record is counted as soon as its meta is read
+    /// (whether or not it is consumed), while the guard following it is read lazily.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Offset of the most recently read record (or EoS) from the start of the stream.
+    pub fn record_offset(&self) -> u64 {
+        self.record_offset
     }
 
     pub fn current_parent(&self) -> Option<RecordId> {
@@ -135,20 +789,213 @@ impl<D: RawDeserialiser, R: Read> MsrfReader<D, R> {
     }
 }
 
+impl<R: Read> MsrfReader<AnyDeserialiser, R> {
+    /// Format version of the current stream.
+    pub fn version(&self) -> u16 {
+        self.des.version()
+    }
+
+    /// Reads the header of the stream following EoS, selecting a deserialiser for its version.
+    ///
+    /// Returns `Ok(None)` if the input ends cleanly after EoS.
+    pub fn next_member(&mut self) -> Result<Option<Header>, ReadError> {
+        if self.poisoned {
+            return Err(self.error(self.position, ParserError::Poisoned));
+        }
+        if !self.is_finished {
+            return Err(self.error(self.position, ParserError::UnexpectedEos));
+        }
+
+        let mut buf = [0; HEADER_LEN];
+        loop {
+            match self.rdr.read(&mut buf[..1]) {
+                Ok(0) => return Ok(None),
+                Ok(_) => break,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(self.error(self.position, e)),
+            }
+        }
+        self.rdr
+            .read_exact(&mut buf[1..])
+            .map_err(|e| self.error(self.position, e))?;
+        self.header_offset = self.position;
+        self.position += HEADER_LEN as u64;
+
+        let mut header = codec::read_header(&buf).map_err(|e| self.error(self.header_offset, e))?;
+        self.des = AnyDeserialiser::new(header.version, self.options.clone()).ok_or_else(|| {
+            self.error(self.header_offset, ParserError::Unsupported(header.version))
+        })?;
+        let mut digest = StreamDigest::new_with_prefix(buf);
+        let rdr = DigestReader::new(&mut self.rdr, Some(&mut digest));
+        self.position += codec::read_header_extension(rdr, &mut header, &self.options)
+            .map_err(|e| self.error(self.header_offset, e))?;
+        let trailer = header.declares(Features::TRAILER);
+        let integrity = header.declares(Features::INTEGRITY);
+        self.digest = (trailer || integrity).then_some(digest);
+        self.stats = trailer.then(Trailer::default);
+        self.trailer = None;
+        self.integrity = integrity.then(IntegrityReader::default);
+        #[cfg(feature = "encryption")]
+        {
+            self.decryption = Decryption::default();
+        }
+        self.header = Some(header.clone());
+        self.is_finished = false;
+        self.pending_guard = false;
+        self.record_span = None;
+        self.depth.clear();
+        self.member += 1;
+        Ok(Some(header))
+    }
+
+    /// Captures the position of the reader, which must be between records.
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            offset: self.position + u64::from(self.pending_guard),
+            header_offset: self.header_offset,
+            records: self.records,
+            version: self.des.version(),
+            member: self.member,
+            depth: self.depth.clone(),
+            state: Vec::new(),
+        }
+    }
+
+    /// Reads the next record, or in concatenated mode, reports the start of the next stream.
+    ///
+    /// Outside of concatenated mode this behaves identically to [`MsrfReader::read_record`].
+    pub fn read_event(&mut self) -> Result<ReadEvent<'_, R>, ReadError> {
+        match self.next_meta() {
+            Ok(meta) => Ok(ReadEvent::Record(meta.into(), self.payload(&meta)?)),
+            Err(e) if e.kind() == ErrorKind::IsEos && self.concatenated => {
+                match self.next_member()? {
+                    Some(header) => Ok(ReadEvent::Member(header)),
+                    None => {
+                        // Input exhausted, further calls should not attempt to read a header
+                        self.concatenated = false;
+                        Err(e)
+                    }
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl<R: Read + Seek> MsrfReader<AnyDeserialiser, R> {
+    /// Resumes reading at a position previously captured by [`MsrfReader::checkpoint`].
+    ///
+    /// The header at [`Checkpoint::header_offset`] must match the checkpointed version, and the
+    /// checkpointed offset must fall on a record boundary.
+    pub fn resume(rdr: R, checkpoint: &Checkpoint) -> Result<Self, ReadError> {
+        Self::resume_with(rdr, checkpoint, DesOptions::default())
+    }
+
+    fn resume_with(
+        rdr: R,
+        checkpoint: &Checkpoint,
+        options: DesOptions,
+    ) -> Result<Self, ReadError> {
+        Self::resume_impl(rdr, checkpoint, options).map_err(|e| {
+            let position = Position::new(checkpoint.offset, checkpoint.records)
+                .with_path(checkpoint.parents());
+            ReadError::new(e, position)
+        })
+    }
+
+    fn resume_impl(
+        mut rdr: R,
+        checkpoint: &Checkpoint,
+        options: DesOptions,
+    ) -> Result<Self, IoError<ParserError>> {
+        let inconsistent = IoError::Parser(ParserError::Checkpoint(checkpoint.offset));
+        if checkpoint.depth.iter().any(|(count, _)| *count == 0) {
+            return Err(inconsistent);
+        }
+
+        let mut buf = [0; HEADER_LEN];
+        rdr.seek(SeekFrom::Start(checkpoint.header_offset))?;
+        rdr.read_exact(&mut buf)?;
+        let mut header = codec::read_header(&buf)?;
+        if header.version != checkpoint.version {
+            return Err(inconsistent);
+        }
+        let des = AnyDeserialiser::new(header.version, options.clone())
+            .ok_or(ParserError::Unsupported(header.version))?;
+        let header_len =
+            HEADER_LEN as u64 + codec::read_header_extension(&mut rdr, &mut header, &options)?;
+        if checkpoint.offset < checkpoint.header_offset + header_len {
+            return Err(inconsistent);
+        }
+
+        // Records always follow a guard (either the header's or the previous record's)
+        rdr.seek(SeekFrom::Start(checkpoint.offset - 1))?;
+        let [guard] = rdr.read_chunk()?;
+        if guard != 0 {
+            return Err(inconsistent);
+        }
+
+        Ok(MsrfReader {
+            is_finished: false,
+            concatenated: false,
+            following: false,
+            member: checkpoint.member,
+            rdr: ReadAhead::new(rdr),
+            des,
+            depth: checkpoint.depth.clone(),
+            position: checkpoint.offset,
+            header: Some(header),
+            records: checkpoint.records,
+            record_id: None,
+            record_span: None,
+            record_offset: checkpoint.offset,
+            header_offset: checkpoint.header_offset,
+            pending_guard: false,
+            options,
+            digest: None,
+            stats: None,
+            trailer: None,
+            integrity: None,
+            #[cfg(feature = "encryption")]
+            decryption: Decryption::default(),
+            payload: PayloadState::default(),
+            poisoned: false,
+            buf: Vec::new(),
+            partial_meta: Vec::new(),
+        })
+    }
+}
+
+fn is_unexpected_eof<T>(res: &Result<T, IoError<ParserError>>) -> bool {
+    matches!(res, Err(IoError::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof)
+}
+
 #[cfg(test)]
 mod test {
-    use std::io::{Cursor, Read};
+    use std::{
+        collections::VecDeque,
+        io::{Cursor, Read},
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
 
     use crate::{
+        Header, RECORD_EOS, RecordId, TYPE_CONTAINER_MASK,
+        checkpoint::Checkpoint,
         codec::{
-            AnyDeserialiser,
+            AnyDeserialiser, DesOptions, Strictness,
             constants::MAGIC_BYTES,
             v0::{
                 self,
-                test::{REF_RECORD_META, REF_RECORD_META_BYTES},
+                test::{
+                    REF_RECORD_META, REF_RECORD_META_BYTES, REF_RECORD_META_CONTAINER,
+                    REF_RECORD_META_CONTAINER_BYTES,
+                },
             },
         },
-        reader::MsrfReader,
+        error::{BuildError, ErrorKind, IoError, ParserError, ReadError},
+        io::{Follow, FollowOptions},
+        reader::{MsrfReader, MsrfReaderBuilder, ReadEvent},
     };
 
     const REF_HEADER_BYTES: &[u8; 7] = constcat::concat_bytes!(
@@ -167,6 +1014,45 @@ mod test {
         assert_eq!(reader.des, AnyDeserialiser::V0(v0::Deserialiser::default()))
     }
 
+    #[test]
+    fn build_detects_version() {
+        let mut data = REF_HEADER_BYTES.to_vec();
+        data.extend_from_slice(&RECORD_EOS.to_le_bytes());
+
+        let mut reader = MsrfReaderBuilder::new()
+            .build(Cursor::new(data.clone()))
+            .expect("failed to build");
+        assert_eq!(reader.version(), 0);
+        assert!(matches!(
+            reader.read_record().map_err(ReadError::into_inner),
+            Err(IoError::Parser(ParserError::IsEos))
+        ));
+
+        assert!(
+            MsrfReaderBuilder::new()
+                .version(0)
+                .build(Cursor::new(data.clone()))
+                .is_ok()
+        );
+        assert!(matches!(
+            MsrfReaderBuilder::new()
+                .version(1)
+                .build(Cursor::new(data.clone())),
+            Err(BuildError::Version {
+                expected: 1,
+                found: 0
+            })
+        ));
+        assert!(matches!(
+            MsrfReaderBuilder::new().version(3).build(Cursor::new(data)),
+            Err(BuildError::Unsupported(3))
+        ));
+        assert!(matches!(
+            MsrfReaderBuilder::new().build(Cursor::new(b"BAD!\0\0\0")),
+            Err(BuildError::Read(e)) if e.kind() == ErrorKind::MagicBytes
+        ));
+    }
+
     #[test]
     fn read_record() {
         let user_data = [1, 2, 3, 4, 5, 6];
@@ -195,4 +1081,674 @@ mod test {
         assert_eq!(1, reader.rdr.read_to_end(&mut guard_buf).expect("io fail"));
         assert_eq!(guard_buf.as_slice(), &[0]);
     }
+
+    #[test]
+    fn read_records() {
+        let user_data = [1, 2, 3, 4, 5, 6];
+        let mut data = REF_HEADER_BYTES.to_vec();
+        for _ in 0..2 {
+            data.extend_from_slice(REF_RECORD_META_BYTES);
+            data.extend_from_slice(&user_data); // User data
+            data.extend_from_slice(&[0]); // Guard
+        }
+        data.extend_from_slice(&RECORD_EOS.to_le_bytes());
+
+        let internal_rdr = Cursor::new(data);
+        let mut reader = MsrfReader::new_unknown(internal_rdr)
+            .initialise()
+            .expect("failed to find deserialiser");
+
+        let record_len = (REF_RECORD_META_BYTES.len() + user_data.len() + 1) as u64;
+        for i in 0..2 {
+            let (id, user_rdr) = reader.read_record().expect("failed to parse record");
+            assert_eq!(id, REF_RECORD_META.into());
+            drop(user_rdr);
+            assert_eq!(
+                reader.record_offset(),
+                REF_HEADER_BYTES.len() as u64 + record_len * i
+            );
+        }
+
+        assert!(matches!(
+            reader.read_record().map_err(ReadError::into_inner),
+            Err(IoError::Parser(ParserError::IsEos))
+        ));
+        assert!(reader.is_finished());
+        assert_eq!(
+            reader.record_offset(),
+            REF_HEADER_BYTES.len() as u64 + record_len * 2
+        );
+    }
+
+    #[test]
+    fn read_record_into() {
+        let mut data = REF_HEADER_BYTES.to_vec();
+        for value in 0..3 {
+            data.extend_from_slice(REF_RECORD_META_BYTES);
+            data.extend_from_slice(&[value; 6]); // User data
+            data.extend_from_slice(&[0]); // Guard
+        }
+        data.truncate(data.len() - 4); // Half the final payload and its guard
+
+        let internal_rdr = Cursor::new(data);
+        let mut reader = MsrfReader::new_unknown(internal_rdr)
+            .initialise()
+            .expect("failed to find deserialiser");
+
+        let mut buf = vec![42];
+        let id = reader
+            .read_record_into(&mut buf)
+            .expect("failed to parse record");
+        assert_eq!(id, REF_RECORD_META.into());
+        assert_eq!(buf, [0; 6]);
+
+        let payload = reader
+            .read_record_with(|id, payload| (id, payload.to_vec()))
+            .expect("failed to parse record");
+        assert_eq!(payload, (REF_RECORD_META.into(), vec![1; 6]));
+
+        let err = reader
+            .read_record_into(&mut buf)
+            .expect_err("succeeded parse");
+        assert_eq!(err.kind(), ErrorKind::Io);
+        assert_eq!(err.position().record(), 2);
+        assert_eq!(buf, [2; 3]);
+    }
+
+    // Counts the reads passed to `R`
+    struct ReadCounter<R> {
+        inner: R,
+        reads: Arc<Mutex<usize>>,
+    }
+
+    impl<R: Read> Read for ReadCounter<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            *self.reads.lock().unwrap() += 1;
+            self.inner.read(buf)
+        }
+    }
+
+    #[test]
+    fn read_buffered_meta() {
+        // Metas straddle the end of the read-ahead buffer
+        let mut data = REF_HEADER_BYTES.to_vec();
+        for value in 0..2000 {
+            data.extend_from_slice(REF_RECORD_META_BYTES);
+            data.extend_from_slice(&[value as u8; 6]); // User data
+            data.extend_from_slice(&[0]); // Guard
+        }
+        data.extend_from_slice(&RECORD_EOS.to_le_bytes());
+        let data_len = data.len();
+
+        let reads = Arc::new(Mutex::new(0));
+        let internal_rdr = ReadCounter {
+            inner: Cursor::new(data),
+            reads: reads.clone(),
+        };
+        let mut reader = MsrfReader::new_unknown(internal_rdr)
+            .initialise()
+            .expect("failed to find deserialiser");
+
+        for value in 0..2000 {
+            let payload = reader
+                .read_record_with(|_, payload| payload.to_vec())
+                .expect("failed to parse record");
+            assert_eq!(payload, [value as u8; 6]);
+        }
+        let err = reader.read_record().err().expect("succeeded parse");
+        assert_eq!(err.kind(), ErrorKind::IsEos);
+        assert_eq!(reader.position(), data_len as u64);
+        assert!(*reads.lock().unwrap() < data_len / 1024);
+    }
+
+    #[test]
+    fn read_record_span() {
+        let data = container_data();
+        let len = data.len() as u64;
+        let mut reader = MsrfReader::new_unknown(Cursor::new(data))
+            .initialise()
+            .expect("failed to find deserialiser");
+        assert_eq!(reader.record_span(), None);
+        assert_eq!(reader.position(), REF_HEADER_BYTES.len() as u64);
+
+        let meta_len = REF_RECORD_META_BYTES.len() as u64;
+        let container_meta_len = REF_RECORD_META_CONTAINER_BYTES.len() as u64;
+        let mut offset = REF_HEADER_BYTES.len() as u64;
+        for i in 0..6 {
+            let (_, user_rdr) = reader.read_record().expect("failed to parse record");
+            drop(user_rdr);
+
+            let span = reader.record_span().expect("missing span");
+            let meta_len = if i == 0 { container_meta_len } else { meta_len };
+            assert_eq!(span.meta_offset(), offset);
+            assert_eq!(span.payload_offset(), offset + meta_len);
+            assert_eq!(span.payload_len(), 6);
+            assert_eq!(span.payload_end(), reader.position());
+            offset = span.payload_end() + 1;
+        }
+
+        assert!(reader.read_record().is_err());
+        assert_eq!(reader.record_span(), None);
+        assert_eq!(reader.position(), len);
+    }
+
+    #[test]
+    fn read_record_invalid_guard() {
+        let mut data = REF_RECORD_META_BYTES.to_vec();
+        data.extend_from_slice(&[1, 2, 3, 4, 5, 6]); // User data
+        data.extend_from_slice(&[42]); // Guard
+        data.extend_from_slice(&RECORD_EOS.to_le_bytes());
+
+        let internal_rdr = Cursor::new(data);
+        let mut reader = MsrfReader::new(internal_rdr, v0::Deserialiser::default());
+
+        reader.read_record().expect("failed to parse record");
+        let err = reader.read_record().err().expect("succeeded parse");
+        assert_eq!(err.position().offset(), 0);
+        assert_eq!(err.position().record(), 0);
+        assert_eq!(err.position().id(), Some(REF_RECORD_META.into()));
+        assert!(matches!(
+            err.into_inner(),
+            IoError::Parser(ParserError::Guard(42))
+        ));
+    }
+
+    fn malformed_data() -> Vec<u8> {
+        let mut data = REF_HEADER_BYTES.to_vec();
+        data.extend_from_slice(&16_u16.to_le_bytes()); // Source ID
+        data.extend_from_slice(&32_u16.to_le_bytes()); // Type ID
+        data.extend_from_slice(&[0b1_1010, 0]); // Length: PV(6), non-minimal
+        data.extend_from_slice(&[0; 6]); // User data
+        data.extend_from_slice(&[0]); // Guard
+        data.extend_from_slice(&16_u16.to_le_bytes()); // Source ID
+        data.extend_from_slice(&(32_u16 | TYPE_CONTAINER_MASK).to_le_bytes()); // Type ID
+        data.extend_from_slice(&[0b1101]); // Length: PV(6)
+        data.extend_from_slice(&0_u16.to_le_bytes()); // Contained: 0
+        data.extend_from_slice(&[0; 6]); // User data
+        data.extend_from_slice(&[42]); // Guard
+        data.extend_from_slice(&16_u16.to_le_bytes()); // Source ID
+        data.extend_from_slice(&32_u16.to_le_bytes()); // Type ID
+        data.extend_from_slice(&[0b1101]); // Length: PV(6)
+        data.extend_from_slice(&[0; 6]); // User data
+        data.extend_from_slice(&[0]); // Guard
+        data.extend_from_slice(&RECORD_EOS.to_le_bytes());
+        data.extend_from_slice(b"trailing");
+        data
+    }
+
+    #[test]
+    fn read_strict() {
+        let mut reader = MsrfReaderBuilder::new()
+            .options(DesOptions::new().strictness(Strictness::Strict))
+            .build_with_unknown(Cursor::new(malformed_data()))
+            .initialise()
+            .expect("failed to find deserialiser");
+
+        let err = reader.read_record().err().expect("succeeded parse");
+        assert_eq!(err.kind(), ErrorKind::Malformed);
+        assert_eq!(err.position().id(), Some(RecordId::new(16, 32)));
+        assert!(matches!(
+            err.into_inner(),
+            IoError::Parser(ParserError::NonMinimal(6))
+        ));
+    }
+
+    #[test]
+    fn read_lenient() {
+        let warnings = Arc::new(Mutex::new(Vec::new()));
+        let options = DesOptions::new()
+            .strictness(Strictness::Lenient)
+            .on_warning({
+                let warnings = warnings.clone();
+                move |e| {
+                    if let IoError::Parser(e) = e.error() {
+                        warnings.lock().unwrap().push(e.clone());
+                    }
+                }
+            });
+        let mut reader = MsrfReaderBuilder::new()
+            .options(options)
+            .build_with_unknown(Cursor::new(malformed_data()))
+            .initialise()
+            .expect("failed to find deserialiser");
+
+        for _ in 0..3 {
+            reader.read_record().expect("failed to parse record");
+        }
+        assert!(matches!(
+            reader.read_record().map_err(ReadError::into_inner),
+            Err(IoError::Parser(ParserError::IsEos))
+        ));
+        assert_eq!(
+            *warnings.lock().unwrap(),
+            [
+                ParserError::NonMinimal(6),
+                ParserError::EmptyContainer,
+                ParserError::Guard(42),
+                ParserError::TrailingData,
+            ]
+        );
+    }
+
+    #[test]
+    fn read_record_error_position() {
+        let mut data = REF_RECORD_META_CONTAINER_BYTES.to_vec();
+        data.extend_from_slice(&[0; 6]); // User data
+        data.extend_from_slice(&[0]); // Guard
+        data.extend_from_slice(&REF_RECORD_META_BYTES[..3]); // Truncated meta
+
+        let internal_rdr = Cursor::new(data);
+        let mut reader = MsrfReader::new(internal_rdr, v0::Deserialiser::default());
+        reader.read_record().expect("failed to parse record");
+
+        let err = reader.read_record().err().expect("succeeded parse");
+        assert_eq!(err.kind(), ErrorKind::Io);
+        assert_eq!(
+            err.position().offset(),
+            (REF_RECORD_META_CONTAINER_BYTES.len() + 7) as u64
+        );
+        assert_eq!(err.position().record(), 1);
+        assert_eq!(err.position().id(), None);
+        assert_eq!(err.position().path(), [REF_RECORD_META_CONTAINER.into()]);
+
+        // Nothing of the truncated meta was consumed, so the read may be retried
+        let err = reader.read_record().err().expect("succeeded parse");
+        assert_eq!(err.kind(), ErrorKind::Io);
+        assert_eq!(
+            err.position().offset(),
+            (REF_RECORD_META_CONTAINER_BYTES.len() + 7) as u64
+        );
+
+        let annotated = reader.annotate(IoError::<ParserError>::Parser(ParserError::Length(6)));
+        assert_eq!(annotated.position().offset(), 0);
+        assert_eq!(annotated.position().record(), 0);
+        assert_eq!(
+            annotated.position().id(),
+            Some(REF_RECORD_META_CONTAINER.into())
+        );
+    }
+
+    // Fails once upon reaching `fail_at`
+    struct FailingReader {
+        inner: Cursor<Vec<u8>>,
+        fail_at: Option<u64>,
+    }
+
+    impl Read for FailingReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let Some(fail_at) = self.fail_at else {
+                return self.inner.read(buf);
+            };
+            let len = buf.len().min((fail_at - self.inner.position()) as usize);
+            if len == 0 {
+                self.fail_at = None;
+                return Err(std::io::Error::other("failed read"));
+            }
+            self.inner.read(&mut buf[..len])
+        }
+    }
+
+    #[test]
+    fn read_record_deferred_error() {
+        let mut data = REF_RECORD_META_BYTES.to_vec();
+        data.extend_from_slice(&[1; 6]); // User data
+        data.extend_from_slice(&[0]); // Guard
+        data.extend_from_slice(REF_RECORD_META_BYTES);
+        data.extend_from_slice(&[2; 6]); // User data
+        data.extend_from_slice(&[0]); // Guard
+        data.extend_from_slice(&RECORD_EOS.to_le_bytes());
+
+        let internal_rdr = FailingReader {
+            inner: Cursor::new(data),
+            fail_at: Some(REF_RECORD_META_BYTES.len() as u64 + 3),
+        };
+        let mut reader = MsrfReader::new(internal_rdr, v0::Deserialiser::default());
+
+        // Dropping the payload unread fails to skip it
+        reader.read_record().expect("failed to parse record");
+        let err = reader.read_record().err().expect("succeeded parse");
+        assert_eq!(err.kind(), ErrorKind::Io);
+        assert_eq!(err.position().record(), 0);
+        assert_eq!(err.position().id(), Some(REF_RECORD_META.into()));
+
+        // The remainder of the payload is skipped on retry
+        let (id, mut user_rdr) = reader.read_record().expect("failed to parse record");
+        assert_eq!(id, REF_RECORD_META.into());
+        let mut buf = Vec::new();
+        user_rdr.read_to_end(&mut buf).expect("io fail");
+        assert_eq!(buf, [2; 6]);
+        drop(user_rdr);
+        let err = reader.read_record().err().expect("succeeded parse");
+        assert_eq!(err.kind(), ErrorKind::IsEos);
+    }
+
+    fn concatenated_data() -> Vec<u8> {
+        let mut data = Vec::new();
+        for value in [7, 8] {
+            data.extend_from_slice(REF_HEADER_BYTES);
+            data.extend_from_slice(REF_RECORD_META_BYTES);
+            data.extend_from_slice(&[value; 6]); // User data
+            data.extend_from_slice(&[0]); // Guard
+            data.extend_from_slice(&RECORD_EOS.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn read_concatenated() {
+        let internal_rdr = Cursor::new(concatenated_data());
+        let mut reader = MsrfReaderBuilder::new()
+            .concatenated(true)
+            .build_with_unknown(internal_rdr)
+            .initialise()
+            .expect("failed to find deserialiser");
+
+        for value in [7, 8] {
+            match reader.read_event().expect("failed to read event") {
+                ReadEvent::Record(id, mut user_rdr) => {
+                    assert_eq!(id, REF_RECORD_META.into());
+                    let mut user_buf = Vec::new();
+                    user_rdr.read_to_end(&mut user_buf).expect("io fail");
+                    assert_eq!(user_buf, [value; 6]);
+                }
+                ReadEvent::Member(_) => panic!("expected record"),
+            }
+
+            if value == 7 {
+                match reader.read_event().expect("failed to read event") {
+                    ReadEvent::Member(header) => assert_eq!(header, Header::new(0)),
+                    ReadEvent::Record(..) => panic!("expected member"),
+                }
+                assert_eq!(reader.member(), 1);
+            }
+        }
+
+        assert!(matches!(
+            reader.read_event().map_err(ReadError::into_inner),
+            Err(IoError::Parser(ParserError::IsEos))
+        ));
+        assert!(matches!(
+            reader.read_event().map_err(ReadError::into_inner),
+            Err(IoError::Parser(ParserError::IsEos))
+        ));
+    }
+
+    #[test]
+    fn read_concatenated_disabled() {
+        let internal_rdr = Cursor::new(concatenated_data());
+        let mut reader = MsrfReader::new_unknown(internal_rdr)
+            .initialise()
+            .expect("failed to find deserialiser");
+
+        assert!(matches!(reader.read_event(), Ok(ReadEvent::Record(..))));
+        assert!(matches!(
+            reader.read_event().map_err(ReadError::into_inner),
+            Err(IoError::Parser(ParserError::IsEos))
+        ));
+        assert_eq!(reader.member(), 0);
+    }
+
+    #[test]
+    fn read_concatenated_trailing_data() {
+        let mut data = concatenated_data();
+        data.extend_from_slice(b"garbage");
+
+        let internal_rdr = Cursor::new(data);
+        let mut reader = MsrfReaderBuilder::new()
+            .concatenated(true)
+            .build_with_unknown(internal_rdr)
+            .initialise()
+            .expect("failed to find deserialiser");
+
+        for _ in 0..3 {
+            reader.read_event().expect("failed to read event");
+        }
+        assert!(matches!(
+            reader.read_event().map_err(ReadError::into_inner),
+            Err(IoError::Parser(ParserError::MagicBytes(_)))
+        ));
+    }
+
+    // Yields each part in turn, reporting EOF between parts to mimic a growing file
+    struct Growing(VecDeque<Vec<u8>>, bool);
+
+    impl Read for Growing {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.1 = !self.1;
+            match self.0.front_mut() {
+                Some(part) if self.1 => {
+                    let len = buf.len().min(part.len());
+                    buf[..len].copy_from_slice(&part[..len]);
+                    part.drain(..len);
+                    if part.is_empty() {
+                        self.0.pop_front();
+                    }
+                    Ok(len)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn read_follow() {
+        let user_data = [1, 2, 3, 4, 5, 6];
+        let mut data = REF_HEADER_BYTES.to_vec();
+        data.extend_from_slice(REF_RECORD_META_BYTES);
+        data.extend_from_slice(&user_data); // User data
+        data.extend_from_slice(&[0]); // Guard
+        data.extend_from_slice(&RECORD_EOS.to_le_bytes());
+
+        // Split mid-header, mid-meta, mid-payload and mid-EoS
+        let mut parts = VecDeque::new();
+        let mut start = 0;
+        for end in [3, 9, 14, 20, data.len()] {
+            parts.push_back(data[start..end].to_vec());
+            start = end;
+        }
+
+        let options = FollowOptions::new().poll_interval(Duration::from_millis(1));
+        let internal_rdr = Follow::new(Growing(parts, false), options);
+        let mut reader = MsrfReader::new_unknown(internal_rdr)
+            .initialise()
+            .expect("failed to find deserialiser");
+
+        let (id, mut user_rdr) = reader.read_record().expect("failed to parse record");
+        assert_eq!(id, REF_RECORD_META.into());
+        let mut user_buf = Vec::new();
+        user_rdr.read_to_end(&mut user_buf).expect("io fail");
+        assert_eq!(user_buf, user_data);
+        drop(user_rdr);
+
+        assert!(matches!(
+            reader.read_record().map_err(ReadError::into_inner),
+            Err(IoError::Parser(ParserError::IsEos))
+        ));
+    }
+
+    #[test]
+    fn read_follow_timeout() {
+        let data = REF_RECORD_META_BYTES[..3].to_vec();
+        let options = FollowOptions::new()
+            .poll_interval(Duration::from_millis(1))
+            .timeout(Duration::from_millis(10));
+        let mut reader =
+            MsrfReader::new(Cursor::new(data), v0::Deserialiser::default()).follow(options);
+
+        match reader.read_record().map_err(ReadError::into_inner) {
+            Err(IoError::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::TimedOut),
+            _ => panic!("expected timeout"),
+        }
+    }
+
+    // Reads whatever has been appended so far, reporting EOF otherwise
+    struct Appended(Arc<Mutex<VecDeque<u8>>>);
+
+    impl Read for Appended {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().read(buf)
+        }
+    }
+
+    #[test]
+    fn read_follow_timeout_retry() {
+        let user_data = [1, 2, 3, 4, 5, 6];
+        let data = Arc::new(Mutex::new(VecDeque::from(
+            REF_RECORD_META_BYTES[..3].to_vec(),
+        )));
+        let options = FollowOptions::new()
+            .poll_interval(Duration::from_millis(1))
+            .timeout(Duration::from_millis(10));
+        let mut reader =
+            MsrfReader::new(Appended(data.clone()), v0::Deserialiser::default()).follow(options);
+
+        // Timing out mid-meta consumes nothing
+        let Err(err) = reader.read_record() else {
+            panic!("expected timeout");
+        };
+        assert_eq!(err.kind(), ErrorKind::Io);
+        assert_eq!(err.position().offset(), 0);
+        assert_eq!(reader.position(), 0);
+
+        data.lock()
+            .unwrap()
+            .extend(REF_RECORD_META_BYTES[3..].iter().chain(&user_data));
+        let (id, mut user_rdr) = reader.read_record().expect("failed to parse record");
+        assert_eq!(id, REF_RECORD_META.into());
+        let mut user_buf = Vec::new();
+        user_rdr.read_to_end(&mut user_buf).expect("io fail");
+        assert_eq!(user_buf, user_data);
+    }
+
+    fn container_data() -> Vec<u8> {
+        let mut data = REF_HEADER_BYTES.to_vec();
+        data.extend_from_slice(REF_RECORD_META_CONTAINER_BYTES);
+        data.extend_from_slice(&[0; 6]); // User data
+        data.extend_from_slice(&[0]); // Guard
+        for value in 0..5 {
+            data.extend_from_slice(REF_RECORD_META_BYTES);
+            data.extend_from_slice(&[value; 6]); // User data
+            data.extend_from_slice(&[0]); // Guard
+        }
+        data.extend_from_slice(&RECORD_EOS.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn read_limits() {
+        let read = |options: DesOptions| {
+            let mut reader = MsrfReaderBuilder::new()
+                .options(options)
+                .build_with_unknown(Cursor::new(container_data()))
+                .initialise()
+                .expect("failed to find deserialiser");
+            let e = loop {
+                if let Err(e) = reader.read_record() {
+                    break e.into_inner();
+                }
+            };
+            let next = reader.read_record().err().map(|e| e.kind());
+            (e, reader.records(), next)
+        };
+
+        let limits = [
+            (
+                DesOptions::new().max_record_len(5),
+                ParserError::RecordLength(6),
+                0,
+            ),
+            (
+                DesOptions::new().max_children(4),
+                ParserError::Children(5),
+                0,
+            ),
+            (DesOptions::new().max_depth(0), ParserError::Depth(1), 0),
+            (DesOptions::new().max_records(3), ParserError::Records(4), 3),
+        ];
+        for (options, error, records) in limits {
+            let (e, read_records, next) = read(options);
+            assert_eq!(e.kind(), ErrorKind::Limit);
+            assert!(matches!(e, IoError::Parser(ref e) if *e == error));
+            assert_eq!(read_records, records);
+            // The meta was consumed, so the reader cannot continue
+            assert_eq!(next, Some(ErrorKind::Poisoned));
+        }
+
+        let options = DesOptions::new()
+            .max_record_len(6)
+            .max_children(5)
+            .max_depth(1)
+            .max_records(6);
+        let (e, read_records, _) = read(options);
+        assert!(matches!(e, IoError::Parser(ParserError::IsEos)));
+        assert_eq!(read_records, 6);
+    }
+
+    #[test]
+    fn read_resume() {
+        let data = container_data();
+        let mut reader = MsrfReader::new_unknown(Cursor::new(data.clone()))
+            .initialise()
+            .expect("failed to find deserialiser");
+
+        for _ in 0..3 {
+            reader.read_record().expect("failed to parse record");
+        }
+        let checkpoint = reader.checkpoint();
+        assert_eq!(checkpoint.parents().count(), 1);
+
+        let mut buf = Vec::new();
+        checkpoint.write_into(&mut buf).expect("ser fail");
+        let checkpoint = Checkpoint::read_from(buf.as_slice()).expect("des fail");
+
+        let mut resumed =
+            MsrfReader::resume(Cursor::new(data), &checkpoint).expect("failed to resume");
+        for value in 2..5 {
+            let (id, mut user_rdr) = reader.read_record().expect("failed to parse record");
+            let (resumed_id, mut resumed_user_rdr) =
+                resumed.read_record().expect("failed to parse record");
+            assert_eq!(id, resumed_id);
+
+            let mut user_buf = Vec::new();
+            resumed_user_rdr
+                .read_to_end(&mut user_buf)
+                .expect("io fail");
+            assert_eq!(user_buf, [value; 6]);
+            user_rdr.drain().expect("io fail");
+
+            drop(user_rdr);
+            drop(resumed_user_rdr);
+            assert_eq!(reader.record_offset(), resumed.record_offset());
+            assert!(reader.parents().eq(resumed.parents()));
+        }
+
+        assert!(matches!(
+            resumed.read_record().map_err(ReadError::into_inner),
+            Err(IoError::Parser(ParserError::IsEos))
+        ));
+    }
+
+    #[test]
+    fn read_resume_inconsistent() {
+        let data = container_data();
+        let mut reader = MsrfReader::new_unknown(Cursor::new(data.clone()))
+            .initialise()
+            .expect("failed to find deserialiser");
+        reader.read_record().expect("failed to parse record");
+
+        let mut checkpoint = reader.checkpoint();
+        checkpoint.offset += 1;
+        assert!(matches!(
+            MsrfReader::resume(Cursor::new(data.clone()), &checkpoint)
+                .map_err(ReadError::into_inner),
+            Err(IoError::Parser(ParserError::Checkpoint(_)))
+        ));
+
+        let mut checkpoint = reader.checkpoint();
+        checkpoint.version = 1;
+        assert!(matches!(
+            MsrfReader::resume(Cursor::new(data), &checkpoint).map_err(ReadError::into_inner),
+            Err(IoError::Parser(ParserError::Checkpoint(_)))
+        ));
+    }
 }
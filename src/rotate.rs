@@ -0,0 +1,535 @@
+use std::io::Write;
+
+use crate::{
+    IntoMetadata, OwnedRecord, RecordId,
+    codec::{AnySerialiser, IntoData, RawSerialiser},
+    error::{BatchError, IoError, ParserError},
+    io::{CountingWriter, RecordSink},
+    writer::{HeaderInit, MsrfWriter, MsrfWriterBuilder},
+};
+
+pub type SegmentWriter<W> = MsrfWriter<AnySerialiser, CountingWriter<W>, HeaderInit>;
+type SegmentSink<W> = RecordSink<CountingWriter<W>>;
+
+/// Thresholds after which the current segment is closed.
+///
+/// A segment is only closed between top-level records, so a container and its children are
+/// never split across segments (and may overshoot a limit as a result).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RotationLimits {
+    max_bytes: Option<u64>,
+    max_records: Option<u64>,
+}
+
+impl RotationLimits {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bytes per segment, including the header.
+    #[must_use]
+    pub fn max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Records per segment, excluding records written by a [`SegmentHook`].
+    #[must_use]
+    pub fn max_records(mut self, max_records: u64) -> Self {
+        self.max_records = Some(max_records);
+        self
+    }
+
+    fn is_exceeded(&self, bytes: u64, records: u64) -> bool {
+        self.max_bytes.is_some_and(|max| bytes >= max)
+            || self.max_records.is_some_and(|max| records >= max)
+    }
+}
+
+/// Invoked after the header of every segment has been written, allowing each segment to be
+/// self-describing (e.g. by replaying registered sources).
+pub trait SegmentHook<S: RawSerialiser, W: Write> {
+    fn segment_start(
+        &mut self,
+        wtr: &mut MsrfWriter<S, W, HeaderInit>,
+    ) -> Result<(), IoError<ParserError>>;
+}
+
+impl<S: RawSerialiser, W: Write> SegmentHook<S, W> for () {
+    fn segment_start(
+        &mut self,
+        _wtr: &mut MsrfWriter<S, W, HeaderInit>,
+    ) -> Result<(), IoError<ParserError>> {
+        Ok(())
+    }
+}
+
+/// Dropping the writer finishes the current segment as [`MsrfWriter`] does.
+pub struct RotatingMsrfWriter<W: Write, F, H = ()> {
+    builder: MsrfWriterBuilder,
+    limits: RotationLimits,
+    open: F,
+    hook: H,
+    segment: usize,
+    records: u64,
+    wtr: SegmentWriter<W>,
+}
+
+impl<W, F> RotatingMsrfWriter<W, F, ()>
+where
+    W: Write,
+    F: FnMut(usize) -> std::io::Result<W>,
+{
+    pub fn new(
+        builder: MsrfWriterBuilder,
+        limits: RotationLimits,
+        open: F,
+    ) -> Result<Self, IoError<ParserError>> {
+        Self::with_hook(builder, limits, open, ())
+    }
+}
+
+impl<W, F, H> RotatingMsrfWriter<W, F, H>
+where
+    W: Write,
+    F: FnMut(usize) -> std::io::Result<W>,
+    H: SegmentHook<AnySerialiser, CountingWriter<W>>,
+{
+    pub fn with_hook(
+        builder: MsrfWriterBuilder,
+        limits: RotationLimits,
+        mut open: F,
+        mut hook: H,
+    ) -> Result<Self, IoError<ParserError>> {
+        let wtr = Self::open_segment(&builder, &mut open, &mut hook, 0)?;
+        Ok(Self {
+            builder,
+            limits,
+            open,
+            hook,
+            segment: 0,
+            records: 0,
+            wtr,
+        })
+    }
+
+    fn open_segment(
+        builder: &MsrfWriterBuilder,
+        open: &mut F,
+        hook: &mut H,
+        segment: usize,
+    ) -> Result<SegmentWriter<W>, IoError<ParserError>> {
+        let inner = CountingWriter::new(open(segment)?);
+        let mut wtr = builder.clone().build(inner)?.initialise()?;
+        hook.segment_start(&mut wtr)?;
+        Ok(wtr)
+    }
+
+    /// Closes the current segment and opens the next, regardless of limits.
+    ///
+    /// Fails with [`ParserError::UnexpectedEos`] if called within a container. If the next
+    /// segment cannot be opened, writes fail with [`ParserError::IsEos`] until a later rotation
+    /// succeeds.
+    pub fn rotate(&mut self) -> Result<(), IoError<ParserError>> {
+        if self.wtr.current_parent().is_some() {
+            return Err(IoError::Parser(ParserError::UnexpectedEos));
+        }
+
+        self.wtr.finish_in_place()?;
+        let segment = self.segment + 1;
+        self.wtr = Self::open_segment(&self.builder, &mut self.open, &mut self.hook, segment)?;
+        self.segment = segment;
+        self.records = 0;
+        Ok(())
+    }
+
+    fn prepare(&mut self) -> Result<(), IoError<ParserError>> {
+        let bytes = self.wtr.get_ref().count();
+        if self.records > 0
+            && self.wtr.current_parent().is_none()
+            && self.limits.is_exceeded(bytes, self.records)
+        {
+            self.rotate()?;
+        }
+        Ok(())
+    }
+
+    pub fn write_record(
+        &mut self,
+        user_data: impl IntoData<AnySerialiser, SegmentSink<W>> + IntoMetadata<AnySerialiser>,
+        source_id: u64,
+    ) -> Result<(), IoError<ParserError>> {
+        self.prepare()?;
+        self.wtr.write_record(user_data, source_id)?;
+        self.records += 1;
+        Ok(())
+    }
+
+    pub fn write_record_with(
+        &mut self,
+        user_data: impl IntoData<AnySerialiser, SegmentSink<W>>,
+        id: RecordId,
+    ) -> Result<(), IoError<ParserError>> {
+        self.prepare()?;
+        self.wtr.write_record_with(user_data, id)?;
+        self.records += 1;
+        Ok(())
+    }
+
+    pub fn write_container(
+        &mut self,
+        user_data: impl IntoData<AnySerialiser, SegmentSink<W>> + IntoMetadata<AnySerialiser>,
+        source_id: u64,
+        length: u64,
+    ) -> Result<(), IoError<ParserError>> {
+        self.prepare()?;
+        self.wtr.write_container(user_data, source_id, length)?;
+        self.records += 1;
+        Ok(())
+    }
+
+    /// Segments may be rotated between records.
+    pub fn write_records<T>(
+        &mut self,
+        source_id: u64,
+        records: impl IntoIterator<Item = T>,
+    ) -> Result<u64, BatchError>
+    where
+        T: IntoData<AnySerialiser, SegmentSink<W>> + IntoMetadata<AnySerialiser>,
+    {
+        let mut written = 0;
+        for record in records {
+            self.write_record(record, source_id)
+                .map_err(|e| BatchError::new(written, e))?;
+            written += 1;
+        }
+        Ok(written)
+    }
+
+    pub fn write_container_from_iter<P, T>(
+        &mut self,
+        parent: P,
+        source_id: u64,
+        children: impl IntoIterator<Item = T>,
+    ) -> Result<u64, BatchError>
+    where
+        P: IntoData<AnySerialiser, SegmentSink<W>> + IntoMetadata<AnySerialiser>,
+        T: IntoData<AnySerialiser, SegmentSink<W>> + IntoMetadata<AnySerialiser>,
+    {
+        self.prepare().map_err(|e| BatchError::new(0, e))?;
+        let res = self
+            .wtr
+            .write_container_from_iter(parent, source_id, children);
+        self.records += res
+            .as_ref()
+            .map_or_else(BatchError::written, |written| *written);
+        res
+    }
+
+    pub fn write_record_fn(
+        &mut self,
+        id: RecordId,
+        f: impl FnOnce(&mut Vec<u8>) -> std::io::Result<()>,
+    ) -> Result<(), IoError<ParserError>> {
+        self.prepare()?;
+        self.wtr.write_record_fn(id, f)?;
+        self.records += 1;
+        Ok(())
+    }
+
+    pub fn write_owned(&mut self, record: &OwnedRecord) -> Result<(), IoError<ParserError>> {
+        self.prepare()?;
+        self.wtr.write_owned(record)?;
+        self.records += 1;
+        Ok(())
+    }
+
+    /// Finishes the current segment, returning its inner writer.
+    pub fn finish(self) -> Result<W, IoError<ParserError>> {
+        self.wtr.finish().map(CountingWriter::into_inner)
+    }
+
+    /// Index of the segment currently being written, starting from 0.
+    pub fn segment(&self) -> usize {
+        self.segment
+    }
+
+    /// Bytes written to the current segment, including the header.
+    pub fn segment_len(&self) -> u64 {
+        self.wtr.get_ref().count()
+    }
+
+    pub fn current(&self) -> &SegmentWriter<W> {
+        &self.wtr
+    }
+
+    pub fn hook(&self) -> &H {
+        &self.hook
+    }
+
+    pub fn hook_mut(&mut self) -> &mut H {
+        &mut self.hook
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::{cell::RefCell, io::Write, rc::Rc};
+
+    use super::*;
+    use crate::{
+        ConstAssignedId, RECORD_EOS,
+        codec::{
+            RawSerialiser,
+            constants::{HEADER_LEN, MAGIC_BYTES},
+        },
+        io::SizedValue,
+    };
+
+    // Header: 7, EoS: 2
+    const SEGMENT_OVERHEAD: usize = HEADER_LEN + 2;
+    // Source: 2, Type: 2, Length: PV(1), Value: 1, Guard: 1
+    const RECORD_LEN: usize = 7;
+    // Record + Contained: 2
+    const CONTAINER_LEN: usize = RECORD_LEN + 2;
+
+    #[derive(Debug)]
+    pub(crate) struct Value(pub(crate) u8);
+
+    impl ConstAssignedId for Value {
+        const TYPE_ID: u64 = 1;
+    }
+
+    impl<S> SizedValue<S> for Value {
+        fn encoded_len(&self, _ser: &S) -> usize {
+            1
+        }
+    }
+
+    impl<S: RawSerialiser> IntoMetadata<S> for Value {}
+
+    impl<S: RawSerialiser, W: Write> IntoData<S, W> for Value {
+        fn encode_into(
+            &self,
+            wtr: &mut W,
+            _ser: &S,
+            _source_id: u64,
+        ) -> Result<(), IoError<ParserError>> {
+            wtr.write_all(&[self.0])?;
+            Ok(())
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct Segments(Rc<RefCell<Vec<Vec<u8>>>>);
+
+    struct SegmentBuf(Rc<RefCell<Vec<Vec<u8>>>>, usize);
+
+    impl Write for SegmentBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut()[self.1].extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Segments {
+        fn open(&self) -> impl FnMut(usize) -> std::io::Result<SegmentBuf> + use<> {
+            let segments = self.0.clone();
+            move |index| {
+                segments.borrow_mut().push(Vec::new());
+                Ok(SegmentBuf(segments.clone(), index))
+            }
+        }
+
+        fn lens(&self) -> Vec<usize> {
+            self.0.borrow().iter().map(Vec::len).collect()
+        }
+
+        fn assert_framed(&self) {
+            for segment in self.0.borrow().iter() {
+                assert_eq!(&segment[..4], &MAGIC_BYTES);
+                assert_eq!(&segment[segment.len() - 2..], &RECORD_EOS.to_le_bytes());
+            }
+        }
+    }
+
+    #[test]
+    fn rotate_records() {
+        let segments = Segments::default();
+        let limits = RotationLimits::new().max_records(2);
+        let mut wtr =
+            RotatingMsrfWriter::new(MsrfWriterBuilder::new(), limits, segments.open())
+                .expect("failed to open");
+
+        for i in 0..5 {
+            wtr.write_record(Value(i), 1).expect("failed write");
+        }
+        assert_eq!(wtr.segment(), 2);
+
+        // Failed writes are not counted
+        wtr.write_record_fn(RecordId::new(1, 1), |_| {
+            Err(std::io::Error::other("failed"))
+        })
+        .expect_err("unexpected write");
+        wtr.write_record(Value(5), 1).expect("failed write");
+        assert_eq!(wtr.segment(), 2);
+        wtr.finish().expect("failed finish");
+
+        segments.assert_framed();
+        assert_eq!(
+            segments.lens(),
+            [
+                SEGMENT_OVERHEAD + RECORD_LEN * 2,
+                SEGMENT_OVERHEAD + RECORD_LEN * 2,
+                SEGMENT_OVERHEAD + RECORD_LEN * 2,
+            ]
+        );
+    }
+
+    #[test]
+    fn rotate_finishes_first() {
+        let segments = Segments::default();
+        let prev = segments.clone();
+        let mut open = segments.open();
+        let mut failed = false;
+        let open = move |index| {
+            if index > 0 {
+                // The previous segment is complete before the next is opened
+                prev.assert_framed();
+                if !std::mem::replace(&mut failed, true) {
+                    return Err(std::io::Error::other("failed"));
+                }
+            }
+            open(index)
+        };
+        let mut wtr =
+            RotatingMsrfWriter::new(MsrfWriterBuilder::new(), RotationLimits::new(), open)
+                .expect("failed to open");
+
+        wtr.write_record(Value(0), 1).expect("failed write");
+        wtr.rotate().expect_err("opened segment");
+        assert!(matches!(
+            wtr.write_record(Value(1), 1),
+            Err(IoError::Parser(ParserError::IsEos))
+        ));
+        wtr.rotate().expect("failed rotate");
+        wtr.write_record(Value(1), 1).expect("failed write");
+        wtr.finish().expect("failed finish");
+
+        segments.assert_framed();
+        assert_eq!(segments.lens(), [SEGMENT_OVERHEAD + RECORD_LEN; 2]);
+    }
+
+    #[test]
+    fn rotate_bytes() {
+        let segments = Segments::default();
+        let limits = RotationLimits::new().max_bytes((HEADER_LEN + RECORD_LEN * 3) as u64);
+        let mut wtr =
+            RotatingMsrfWriter::new(MsrfWriterBuilder::new(), limits, segments.open())
+                .expect("failed to open");
+
+        for i in 0..4 {
+            wtr.write_record(Value(i), 1).expect("failed write");
+        }
+        wtr.finish().expect("failed finish");
+
+        segments.assert_framed();
+        assert_eq!(
+            segments.lens(),
+            [
+                SEGMENT_OVERHEAD + RECORD_LEN * 3,
+                SEGMENT_OVERHEAD + RECORD_LEN,
+            ]
+        );
+    }
+
+    #[test]
+    fn rotate_preserves_containers() {
+        let segments = Segments::default();
+        let limits = RotationLimits::new().max_records(2);
+        let mut wtr =
+            RotatingMsrfWriter::new(MsrfWriterBuilder::new(), limits, segments.open())
+                .expect("failed to open");
+
+        wtr.write_container(Value(0), 1, 3).expect("failed write");
+        for i in 1..4 {
+            wtr.write_record(Value(i), 1).expect("failed write");
+        }
+        wtr.write_record(Value(4), 1).expect("failed write");
+        wtr.finish().expect("failed finish");
+
+        segments.assert_framed();
+        assert_eq!(
+            segments.lens(),
+            [
+                SEGMENT_OVERHEAD + CONTAINER_LEN + RECORD_LEN * 3,
+                SEGMENT_OVERHEAD + RECORD_LEN,
+            ]
+        );
+    }
+
+    #[test]
+    fn rotate_hook() {
+        struct Replay(u8);
+
+        impl<S: RawSerialiser, W: Write> SegmentHook<S, W> for Replay {
+            fn segment_start(
+                &mut self,
+                wtr: &mut MsrfWriter<S, W, HeaderInit>,
+            ) -> Result<(), IoError<ParserError>> {
+                self.0 += 1;
+                wtr.write_record(Value(self.0), 0)
+            }
+        }
+
+        let segments = Segments::default();
+        let limits = RotationLimits::new().max_records(1);
+        let mut wtr = RotatingMsrfWriter::with_hook(
+            MsrfWriterBuilder::new(),
+            limits,
+            segments.open(),
+            Replay(0),
+        )
+        .expect("failed to open");
+
+        wtr.write_record(Value(0), 1).expect("failed write");
+        wtr.write_record(Value(1), 1).expect("failed write");
+        assert_eq!(wtr.hook().0, 2);
+        wtr.finish().expect("failed finish");
+
+        segments.assert_framed();
+        assert_eq!(
+            segments.lens(),
+            [
+                SEGMENT_OVERHEAD + RECORD_LEN * 2,
+                SEGMENT_OVERHEAD + RECORD_LEN * 2,
+            ]
+        );
+    }
+
+    #[test]
+    fn rotate_drop_unfinished() {
+        let segments = Segments::default();
+        let mut wtr = RotatingMsrfWriter::new(
+            MsrfWriterBuilder::new(),
+            RotationLimits::new().max_records(1),
+            segments.open(),
+        )
+        .expect("failed to open");
+
+        wtr.write_record(Value(0), 1).expect("failed write");
+        wtr.write_record(Value(1), 1).expect("failed write");
+        drop(wtr);
+
+        segments.assert_framed();
+        assert_eq!(
+            segments.lens(),
+            [SEGMENT_OVERHEAD + RECORD_LEN, SEGMENT_OVERHEAD + RECORD_LEN]
+        );
+    }
+}
@@ -0,0 +1,376 @@
+use std::{
+    cmp::Ordering,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    RecordId,
+    codec::{AnyDeserialiser, DesOptions},
+    error::{ErrorKind, ParserError, Position, ReadError},
+    io::RecordChunk,
+    reader::{MsrfReader, MsrfReaderBuilder},
+};
+
+pub type SegmentReader = MsrfReader<AnyDeserialiser, BufReader<File>>;
+pub type SegmentRecord<'a> = (SegmentPos, RecordId, RecordChunk<'a, BufReader<File>>);
+
+/// Location of a record within a segmented dataset.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SegmentPos {
+    /// Index of the segment within the dataset, starting from 0.
+    pub segment: usize,
+    /// Offset of the record from the start of its segment (including the header).
+    pub offset: u64,
+}
+
+/// Reads an ordered list of MSRF files as one logical stream.
+///
+/// Each segment is opened lazily, its header validated, and its EoS consumed transparently.
+/// [`ParserError::IsEos`] is only returned once the final segment is exhausted.
+pub struct SegmentedReader {
+    paths: Vec<PathBuf>,
+    segment: usize,
+    rdr: Option<SegmentReader>,
+    options: DesOptions,
+}
+
+impl SegmentedReader {
+    pub fn new<P: Into<PathBuf>>(paths: impl IntoIterator<Item = P>) -> SegmentedReader {
+        SegmentedReader {
+            paths: paths.into_iter().map(Into::into).collect(),
+            segment: 0,
+            rdr: None,
+            options: DesOptions::default(),
+        }
+    }
+
+    /// Limits and keys used while reading each segment, see [`DesOptions`].
+    #[must_use]
+    pub fn with_options(mut self, options: DesOptions) -> SegmentedReader {
+        self.options = options;
+        self
+    }
+
+    /// Collects all files in `dir` matching `pattern`, ordered by name with embedded numbers
+    /// compared numerically (e.g. `capture-2.msrf` before `capture-10.msrf`).
+    ///
+    /// `pattern` supports `*` (any sequence) and `?` (any single character) wildcards.
+    pub fn from_dir(dir: impl AsRef<Path>, pattern: &str) -> std::io::Result<SegmentedReader> {
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file()
+                && entry
+                    .file_name()
+                    .to_str()
+                    .is_some_and(|name| matches_pattern(pattern, name))
+            {
+                paths.push(entry.path());
+            }
+        }
+
+        paths.sort_by(|lhs, rhs| {
+            natural_cmp(
+                &lhs.file_name().unwrap_or_default().to_string_lossy(),
+                &rhs.file_name().unwrap_or_default().to_string_lossy(),
+            )
+        });
+        Ok(SegmentedReader::new(paths))
+    }
+
+    fn current(&mut self) -> Result<&mut SegmentReader, ReadError> {
+        if self.rdr.is_none() {
+            let path = self
+                .paths
+                .get(self.segment)
+                .ok_or_else(|| ReadError::new(ParserError::IsEos, Position::default()))?;
+            let file = File::open(path).map_err(|e| ReadError::new(e, Position::default()))?;
+            let rdr = BufReader::new(file);
+            let rdr = MsrfReaderBuilder::new()
+                .options(self.options.clone())
+                .build_with_unknown(rdr);
+            self.rdr = Some(rdr.initialise()?);
+        }
+
+        // SAFETY: Populated above
+        Ok(self.rdr.as_mut().unwrap())
+    }
+
+    pub fn read_record(&mut self) -> Result<SegmentRecord<'_>, ReadError> {
+        let meta = loop {
+            let rdr = self.current()?;
+            match rdr.next_meta() {
+                Ok(meta) => break meta,
+                Err(e) if e.kind() == ErrorKind::IsEos && rdr.current_parent().is_some() => {
+                    return Err(ReadError::new(
+                        ParserError::UnexpectedEos,
+                        e.position().clone(),
+                    ));
+                }
+                Err(e) if e.kind() == ErrorKind::IsEos => {
+                    self.rdr = None;
+                    self.segment += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+
+        let pos = SegmentPos {
+            segment: self.segment,
+            offset: self.current()?.record_offset(),
+        };
+        let chunk = self.current()?.payload(&meta)?;
+        Ok((pos, meta.into(), chunk))
+    }
+
+    /// Index of the segment currently being read, starting from 0.
+    pub fn segment(&self) -> usize {
+        self.segment
+    }
+
+    pub fn segment_path(&self) -> Option<&Path> {
+        self.paths.get(self.segment).map(PathBuf::as_path)
+    }
+
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    pub fn current_parent(&self) -> Option<RecordId> {
+        self.rdr.as_ref().and_then(MsrfReader::current_parent)
+    }
+
+    // Top down
+    pub fn parents(&self) -> impl DoubleEndedIterator<Item = RecordId> {
+        self.rdr.iter().flat_map(|rdr| rdr.parents())
+    }
+}
+
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_n)) => {
+                    backtrack = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+fn natural_cmp(lhs: &str, rhs: &str) -> Ordering {
+    let mut lhs = lhs.chars().peekable();
+    let mut rhs = rhs.chars().peekable();
+
+    loop {
+        match (lhs.peek().copied(), rhs.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(l), Some(r)) if l.is_ascii_digit() && r.is_ascii_digit() => {
+                let l_num: String =
+                    std::iter::from_fn(|| lhs.next_if(char::is_ascii_digit)).collect();
+                let r_num: String =
+                    std::iter::from_fn(|| rhs.next_if(char::is_ascii_digit)).collect();
+                let l_trim = l_num.trim_start_matches('0');
+                let r_trim = r_num.trim_start_matches('0');
+                let ord = l_trim
+                    .len()
+                    .cmp(&r_trim.len())
+                    .then_with(|| l_trim.cmp(r_trim))
+                    .then_with(|| l_num.len().cmp(&r_num.len()));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            (Some(l), Some(r)) => {
+                if l != r {
+                    return l.cmp(&r);
+                }
+                lhs.next();
+                rhs.next();
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "writer"))]
+mod test {
+    use std::{
+        fs::File,
+        io::{BufWriter, Read},
+        path::PathBuf,
+    };
+
+    use super::*;
+    #[cfg(feature = "encryption")]
+    use crate::encryption::{EncryptionOptions, KEY_LEN, Key};
+    use crate::{
+        ConstAssignedId,
+        codec::constants::HEADER_LEN,
+        error::IoError,
+        rotate::{RotatingMsrfWriter, RotationLimits, test::Value},
+        writer::MsrfWriterBuilder,
+    };
+
+    #[cfg(feature = "encryption")]
+    const KEY: Key = [7; KEY_LEN];
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("msrf-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("failed to create test dir");
+        dir
+    }
+
+    fn write_segments(dir: &Path, records: u8, per_segment: u64) {
+        write_segments_with(dir, MsrfWriterBuilder::new(), records, per_segment);
+    }
+
+    fn write_segments_with(dir: &Path, builder: MsrfWriterBuilder, records: u8, per_segment: u64) {
+        let limits = RotationLimits::new().max_records(per_segment);
+        let mut wtr = RotatingMsrfWriter::new(builder, limits, |i| {
+            File::create(dir.join(format!("capture-{i}.msrf"))).map(BufWriter::new)
+        })
+        .expect("failed to open");
+
+        for i in 0..records {
+            wtr.write_record(Value(i), 1).expect("failed write");
+        }
+        wtr.finish().expect("failed finish");
+    }
+
+    #[test]
+    fn read_segments() {
+        let dir = test_dir("read_segments");
+        write_segments(&dir, 23, 2);
+        std::fs::write(dir.join("notes.txt"), b"unrelated").expect("failed write");
+
+        let mut rdr = SegmentedReader::from_dir(&dir, "capture-*.msrf").expect("failed to list");
+        assert_eq!(rdr.paths().len(), 12);
+        assert_eq!(rdr.paths()[10], dir.join("capture-10.msrf"));
+
+        // Source: 2, Type: 2, Length: PV(1), Value: 1, Guard: 1
+        const RECORD_LEN: u64 = 7;
+        for i in 0..23 {
+            let (pos, id, mut chunk) = rdr.read_record().expect("failed read");
+            let mut value = Vec::new();
+            chunk.read_to_end(&mut value).expect("io fail");
+
+            assert_eq!(id, RecordId::new(1, Value::TYPE_ID));
+            assert_eq!(value, [i]);
+            assert_eq!(
+                pos,
+                SegmentPos {
+                    segment: i as usize / 2,
+                    offset: HEADER_LEN as u64 + RECORD_LEN * (i as u64 % 2),
+                }
+            );
+        }
+
+        assert!(matches!(
+            rdr.read_record().map_err(ReadError::into_inner),
+            Err(IoError::Parser(ParserError::IsEos))
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn read_segments_invalid_header() {
+        let dir = test_dir("read_segments_invalid_header");
+        write_segments(&dir, 2, 1);
+        std::fs::write(dir.join("capture-2.msrf"), b"BAD!\0\0\0").expect("failed write");
+
+        let mut rdr = SegmentedReader::from_dir(&dir, "capture-?.msrf").expect("failed to list");
+        rdr.read_record().expect("failed read");
+        rdr.read_record().expect("failed read");
+        assert!(matches!(
+            rdr.read_record().map_err(ReadError::into_inner),
+            Err(IoError::Parser(ParserError::MagicBytes(_)))
+        ));
+        assert_eq!(rdr.segment(), 2);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(feature = "encryption")]
+    fn write_encrypted_segments(dir: &Path) {
+        let builder = MsrfWriterBuilder::new()
+            .version(crate::EXTENDED_HEADER_VERSION)
+            .encryption(EncryptionOptions::new().key(1, KEY));
+        write_segments_with(dir, builder, 5, 2);
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn read_segments_encrypted() {
+        let dir = test_dir("read_segments_encrypted");
+        write_encrypted_segments(&dir);
+
+        let options = DesOptions::new().key_provider(|source_id| (source_id == 1).then_some(KEY));
+        let mut rdr = SegmentedReader::from_dir(&dir, "capture-*.msrf")
+            .expect("failed to list")
+            .with_options(options);
+        for i in 0..5 {
+            let (_, _, mut chunk) = rdr.read_record().expect("failed read");
+            let mut value = Vec::new();
+            chunk.read_to_end(&mut value).expect("io fail");
+            assert_eq!(value, [i]);
+        }
+        assert_eq!(rdr.segment(), 2);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn read_segments_wrong_key() {
+        let dir = test_dir("read_segments_wrong_key");
+        write_encrypted_segments(&dir);
+
+        let options = DesOptions::new().key_provider(|_| Some([8; KEY_LEN]));
+        let mut rdr = SegmentedReader::from_dir(&dir, "capture-*.msrf")
+            .expect("failed to list")
+            .with_options(options);
+        let err = rdr.read_record().map(|_| ()).expect_err("succeeded read");
+        assert_eq!(err.kind(), ErrorKind::Integrity);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn pattern() {
+        assert!(matches_pattern("*.msrf", "capture.msrf"));
+        assert!(matches_pattern("capture-?.msrf", "capture-1.msrf"));
+        assert!(matches_pattern("*-*.msrf", "a-b-c.msrf"));
+        assert!(matches_pattern("*", ""));
+        assert!(!matches_pattern("capture-?.msrf", "capture-10.msrf"));
+        assert!(!matches_pattern("*.msrf", "capture.msrf.tmp"));
+    }
+
+    #[test]
+    fn natural_order() {
+        assert_eq!(natural_cmp("seg-2", "seg-10"), Ordering::Less);
+        assert_eq!(natural_cmp("seg-10", "seg-9"), Ordering::Greater);
+        assert_eq!(natural_cmp("seg-02", "seg-2"), Ordering::Greater);
+        assert_eq!(natural_cmp("a", "b"), Ordering::Less);
+        assert_eq!(natural_cmp("seg", "seg-1"), Ordering::Less);
+    }
+}
@@ -0,0 +1,281 @@
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+use sha2::Sha256;
+
+use crate::{
+    RecordMeta,
+    error::{IoError, ParserError},
+    io::{ReadExt, WriteExt},
+};
+
+pub const DIGEST_LEN: usize = 32;
+
+/// Running digest over every byte of a stream, from the header up to and including EoS.
+pub(crate) type StreamDigest = Sha256;
+
+/// Records and payload bytes written by a single source.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SourceStats {
+    pub(crate) records: u64,
+    pub(crate) bytes: u64,
+}
+
+impl SourceStats {
+    #[must_use]
+    pub fn records(&self) -> u64 {
+        self.records
+    }
+
+    /// Payload bytes, excluding metas and guards.
+    #[must_use]
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+}
+
+/// Summary following EoS in streams declaring [`crate::Features::TRAILER`], verified by
+/// [`crate::reader::MsrfReader`] on reaching EoS to detect dropped or altered records.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trailer {
+    pub(crate) records: u64,
+    pub(crate) max_depth: u64,
+    pub(crate) sources: BTreeMap<u64, SourceStats>,
+    pub(crate) digest: [u8; DIGEST_LEN],
+}
+
+impl Trailer {
+    /// Number of records excluding EoS.
+    #[must_use]
+    pub fn records(&self) -> u64 {
+        self.records
+    }
+
+    /// Deepest nesting of non-empty containers.
+    #[must_use]
+    pub fn max_depth(&self) -> u64 {
+        self.max_depth
+    }
+
+    #[must_use]
+    pub fn source(&self, source_id: u64) -> Option<&SourceStats> {
+        self.sources.get(&source_id)
+    }
+
+    /// Ordered by source id.
+    pub fn sources(&self) -> impl Iterator<Item = (u64, &SourceStats)> {
+        self.sources.iter().map(|(id, stats)| (*id, stats))
+    }
+
+    /// SHA-256 of the stream from the header up to and including EoS.
+    #[must_use]
+    pub fn digest(&self) -> &[u8; DIGEST_LEN] {
+        &self.digest
+    }
+
+    // `depth` is that of the writer or reader after accounting for `meta`
+    pub(crate) fn update(&mut self, meta: &RecordMeta, depth: usize) {
+        self.records += 1;
+        self.max_depth = self.max_depth.max(depth as u64);
+        let source = self.sources.entry(meta.source_id()).or_default();
+        source.records += 1;
+        source.bytes += meta.len();
+    }
+
+    pub fn write_into<W: Write>(&self, mut wtr: W) -> Result<(), IoError<ParserError>> {
+        wtr.write_varint(self.records)?;
+        wtr.write_varint(self.max_depth)?;
+        wtr.write_varint(self.sources.len() as u64)?;
+        for (id, stats) in &self.sources {
+            wtr.write_varint(*id)?;
+            wtr.write_varint(stats.records)?;
+            wtr.write_varint(stats.bytes)?;
+        }
+        wtr.write_all(&self.digest)?;
+        wtr.write_all(&[0x00])?;
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(mut rdr: R) -> Result<Trailer, IoError<ParserError>> {
+        let records = rdr.read_varint()?;
+        let max_depth = rdr.read_varint()?;
+
+        let sources_len = rdr.read_varint()?;
+        let mut sources = BTreeMap::new();
+        for _ in 0..sources_len {
+            let id = rdr.read_varint()?;
+            let stats = SourceStats {
+                records: rdr.read_varint()?,
+                bytes: rdr.read_varint()?,
+            };
+            sources.insert(id, stats);
+        }
+
+        let digest = rdr.read_chunk()?;
+        let [guard] = rdr.read_chunk()?;
+        if guard != 0 {
+            return Err(IoError::Parser(ParserError::Guard(guard)));
+        }
+
+        Ok(Trailer {
+            records,
+            max_depth,
+            sources,
+            digest,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn serdes_trailer() {
+        let mut trailer = Trailer::default();
+        trailer.update(&RecordMeta::new_container(2, 0, 6, 1), 1);
+        trailer.update(&RecordMeta::new(1, 0, 3), 0);
+        trailer.update(&RecordMeta::new(2, 0, 4), 0);
+        trailer.digest = [7; DIGEST_LEN];
+
+        let mut buf = Vec::new();
+        trailer.write_into(&mut buf).expect("ser fail");
+        assert_eq!(buf.len(), 3 + 3 * 2 + DIGEST_LEN + 1);
+        assert_eq!(
+            Trailer::read_from(buf.as_slice()).expect("des fail"),
+            trailer
+        );
+
+        assert_eq!(trailer.records(), 3);
+        assert_eq!(trailer.max_depth(), 1);
+        assert_eq!(
+            trailer.source(2),
+            Some(&SourceStats {
+                records: 2,
+                bytes: 10
+            })
+        );
+        assert_eq!(
+            trailer.sources().map(|(id, _)| id).collect::<Vec<_>>(),
+            [1, 2]
+        );
+    }
+
+    #[cfg(all(feature = "reader", feature = "writer"))]
+    fn write_stream() -> Vec<u8> {
+        use crate::{
+            EXTENDED_HEADER_VERSION, Features, Header, rotate::test::Value,
+            writer::MsrfWriterBuilder,
+        };
+
+        let mut wtr = MsrfWriterBuilder::new()
+            .header(Header::new(EXTENDED_HEADER_VERSION).with_optional(Features::TRAILER))
+            .build(Vec::new())
+            .expect("unsupported version")
+            .initialise()
+            .expect("failed to initialise");
+        wtr.write_container(Value(0), 1, 1).expect("failed write");
+        wtr.write_record(Value(1), 2).expect("failed write");
+        wtr.write_record(Value(2), 2).expect("failed write");
+        wtr.finish().expect("failed finish")
+    }
+
+    #[cfg(all(feature = "reader", feature = "writer"))]
+    fn read_stream(data: Vec<u8>) -> Result<Option<Trailer>, crate::error::ReadError> {
+        use std::io::{Cursor, Read};
+
+        use crate::{error::ErrorKind, reader::MsrfReaderBuilder};
+
+        let mut rdr = MsrfReaderBuilder::new()
+            .build(Cursor::new(data))
+            .expect("failed to build");
+        loop {
+            let res = rdr.read_record().map(|(_, mut user_rdr)| {
+                user_rdr.read_to_end(&mut Vec::new()).expect("io fail");
+            });
+            match res {
+                Ok(()) => {}
+                Err(e) if e.kind() == ErrorKind::IsEos => return Ok(rdr.trailer().cloned()),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    #[cfg(all(feature = "reader", feature = "writer"))]
+    #[test]
+    fn verify_trailer() {
+        let trailer = read_stream(write_stream())
+            .expect("failed verify")
+            .expect("missing trailer");
+        assert_eq!(trailer.records(), 3);
+        assert_eq!(trailer.max_depth(), 1);
+        assert_eq!(trailer.source(1).map(SourceStats::bytes), Some(1));
+        assert_eq!(trailer.source(2).map(SourceStats::records), Some(2));
+    }
+
+    #[cfg(all(feature = "reader", feature = "writer"))]
+    #[test]
+    fn verify_trailer_altered() {
+        use crate::error::ErrorKind;
+
+        // Final payload byte, as the final record precedes the guard, EoS and trailer
+        let mut data = write_stream();
+        let trailer_len = 3 + 3 * 2 + DIGEST_LEN + 1;
+        let payload = data.len() - trailer_len - 3 - 2;
+        data[payload] ^= 1;
+        let err = read_stream(data).expect_err("succeeded verify");
+        assert_eq!(err.kind(), ErrorKind::Integrity);
+    }
+
+    #[cfg(all(feature = "reader", feature = "writer"))]
+    #[test]
+    fn resume_skips_trailer() {
+        use std::io::Cursor;
+
+        use crate::{
+            codec::{DesOptions, Strictness},
+            error::ErrorKind,
+            reader::{MsrfReaderBuilder, ReadEvent},
+        };
+
+        let data = [write_stream(), write_stream()].concat();
+        let builder =
+            MsrfReaderBuilder::new().options(DesOptions::new().strictness(Strictness::Strict));
+        let mut rdr = builder
+            .clone()
+            .build(Cursor::new(data.clone()))
+            .expect("failed to build");
+        rdr.read_record().expect("failed to parse record");
+        let checkpoint = rdr.checkpoint();
+
+        // The trailer is not mistaken for trailing data
+        let mut resumed = builder
+            .clone()
+            .resume(Cursor::new(&data[..data.len() / 2]), &checkpoint)
+            .expect("failed to resume");
+        for _ in 0..2 {
+            resumed.read_record().expect("failed to parse record");
+        }
+        let err = resumed.read_record().err().expect("read past eos");
+        assert_eq!(err.kind(), ErrorKind::IsEos);
+        assert!(resumed.trailer().is_none());
+
+        // Nor as the start of the next member
+        let mut resumed = builder
+            .resume(Cursor::new(data), &checkpoint)
+            .expect("failed to resume")
+            .with_concatenated(true);
+        let mut records = 0;
+        loop {
+            match resumed.read_event() {
+                Ok(ReadEvent::Record(..)) => records += 1,
+                Ok(ReadEvent::Member(_)) => {}
+                Err(e) if e.kind() == ErrorKind::IsEos => break,
+                Err(e) => panic!("failed to read: {e}"),
+            }
+        }
+        assert_eq!(records, 5);
+        assert_eq!(resumed.member(), 1);
+        assert!(resumed.trailer().is_some());
+    }
+}
@@ -1,23 +1,28 @@
-use std::{fmt::Debug, io::Write, marker::PhantomData};
+use std::{fmt::Debug, io::Write, marker::PhantomData, sync::Arc};
 
 use crate::{
-    CURRENT_VERSION, Header, IntoMetadata, RecordId, RecordMeta,
+    CURRENT_VERSION, EXTENDED_HEADER_VERSION, Features, Header, IntoMetadata, OwnedRecord, RecordId,
+    RecordMeta, RecordSpan,
     codec::{self, AnySerialiser, IntoData, RawSerialiser},
-    error::{IoError, ParserError},
-    io::SizedValue,
+    error::{BatchError, BuildError, IoError, ParserError},
+    integrity::{DIGEST_RECORD, IntegrityOptions, IntegrityWriter},
+    io::{CountingWriter, RecordSink, SizedValue},
+    trailer::Trailer,
 };
+#[cfg(feature = "encryption")]
+use crate::encryption::{self, EncryptionOptions};
 
-#[derive(Debug, Clone)]
-pub struct MsrfWriterBuilder {
-    version: u16,
-}
+/// Invoked with the error if a writer dropped without calling [`MsrfWriter::finish`] fails to
+/// finish the stream.
+pub type DropHook = Arc<dyn Fn(&IoError<ParserError>) + Send + Sync>;
 
-impl Default for MsrfWriterBuilder {
-    fn default() -> Self {
-        Self {
-            version: CURRENT_VERSION,
-        }
-    }
+#[derive(Clone, Default)]
+pub struct MsrfWriterBuilder {
+    header: Header,
+    integrity: Option<IntegrityOptions>,
+    #[cfg(feature = "encryption")]
+    encryption: Option<EncryptionOptions>,
+    on_drop_error: Option<DropHook>,
 }
 
 // TODO: Smart version handling (track statically if valid)
@@ -27,27 +32,109 @@ impl MsrfWriterBuilder {
         MsrfWriterBuilder::default()
     }
 
+    /// Format version written to the header, validated by [`MsrfWriterBuilder::build`].
     #[must_use]
-    pub fn version(mut self, version: u16) -> Option<MsrfWriterBuilder> {
-        if version > CURRENT_VERSION {
-            return None;
-        }
-        self.version = version;
-        Some(self)
+    pub fn version(mut self, version: u16) -> MsrfWriterBuilder {
+        self.header.version = version;
+        self
+    }
+
+    /// Header written at the start of the stream, replacing the version set by
+    /// [`MsrfWriterBuilder::version`]. An extended header requires [`EXTENDED_HEADER_VERSION`].
+    #[must_use]
+    pub fn header(mut self, header: Header) -> MsrfWriterBuilder {
+        self.header = header;
+        self
+    }
+
+    /// Interleaves digest records, declaring [`Features::INTEGRITY`] as required. A header
+    /// already declaring it uses the default options. Requires [`EXTENDED_HEADER_VERSION`].
+    #[must_use]
+    pub fn integrity(mut self, options: IntegrityOptions) -> MsrfWriterBuilder {
+        self.integrity = Some(options);
+        self
+    }
+
+    /// Encrypts the payloads of sources with a key, listing them in
+    /// [`Header::FIELD_ENCRYPTED`] and declaring [`Features::ENCRYPTION`] as optional. Requires
+    /// [`EXTENDED_HEADER_VERSION`].
+    #[cfg(feature = "encryption")]
+    #[must_use]
+    pub fn encryption(mut self, options: EncryptionOptions) -> MsrfWriterBuilder {
+        self.encryption = Some(options);
+        self
+    }
+
+    /// Reports failure to finish the stream when dropped unfinished, which is otherwise ignored.
+    ///
+    /// A writer is left unfinished (failing with [`ParserError::Poisoned`] or
+    /// [`ParserError::UnexpectedEos`]) if a failed write left the stream mid-record, or a
+    /// container is still open.
+    #[must_use]
+    pub fn on_drop_error(
+        mut self,
+        on_drop_error: impl Fn(&IoError<ParserError>) + Send + Sync + 'static,
+    ) -> MsrfWriterBuilder {
+        self.on_drop_error = Some(Arc::new(on_drop_error));
+        self
     }
 
-    // TODO: Error
-    pub fn build<W: Write>(self, wtr: W) -> Option<MsrfWriter<AnySerialiser, W, HeaderUninit>> {
-        let ser = AnySerialiser::new_default(self.version)?;
-        Some(MsrfWriter::new(wtr, ser))
+    pub fn build<W: Write>(
+        self,
+        wtr: W,
+    ) -> Result<MsrfWriter<AnySerialiser, W, HeaderUninit>, BuildError> {
+        let builder = self.validate()?;
+        let ser = AnySerialiser::new_default(builder.header.version)
+            .ok_or(BuildError::Unsupported(builder.header.version))?;
+        Ok(MsrfWriter::new(wtr, ser, builder))
     }
 
+    /// `ser` must encode records as described by [`MsrfWriterBuilder::version`].
     pub fn build_with<W: Write, S: RawSerialiser>(
         self,
         wtr: W,
         ser: S,
-    ) -> MsrfWriter<S, W, HeaderUninit> {
-        MsrfWriter::new(wtr, ser)
+    ) -> Result<MsrfWriter<S, W, HeaderUninit>, BuildError> {
+        Ok(MsrfWriter::new(wtr, ser, self.validate()?))
+    }
+
+    // Declares the features of the options set in the header
+    fn validate(mut self) -> Result<Self, BuildError> {
+        if self.integrity.is_some() {
+            self.header.required = self.header.required | Features::INTEGRITY;
+        } else if self.header.declares(Features::INTEGRITY) {
+            self.integrity = Some(IntegrityOptions::default());
+        }
+        #[cfg(feature = "encryption")]
+        if let Some(encryption) = &self.encryption
+            && !encryption.keys.is_empty()
+        {
+            self.header.optional = self.header.optional | Features::ENCRYPTION;
+            self.header = self
+                .header
+                .with_field(Header::FIELD_ENCRYPTED, encryption.field());
+        }
+
+        let version = self.header.version;
+        if version > CURRENT_VERSION {
+            Err(BuildError::Unsupported(version))
+        } else if version < EXTENDED_HEADER_VERSION && self.header.is_extended() {
+            Err(BuildError::Extended(version))
+        } else {
+            Ok(self)
+        }
+    }
+}
+
+impl Debug for MsrfWriterBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut f = f.debug_struct("MsrfWriterBuilder");
+        f.field("header", &self.header)
+            .field("integrity", &self.integrity);
+        #[cfg(feature = "encryption")]
+        f.field("encryption", &self.encryption);
+        f.field("on_drop_error", &self.on_drop_error.as_ref().map(|_| ".."))
+            .finish()
     }
 }
 
@@ -57,46 +144,111 @@ pub struct HeaderInit;
 pub struct HeaderUninit;
 
 // TODO: Config
-pub struct MsrfWriter<S, W, H> {
+/// Dropping an initialised writer without calling [`MsrfWriter::finish`] finishes the stream
+/// on a best effort basis, see [`MsrfWriterBuilder::on_drop_error`] and
+/// [`MsrfWriter::into_inner_unfinished`].
+pub struct MsrfWriter<S: RawSerialiser, W: Write, H> {
+    // Taken once finished or unwrapped, so is always present while borrowed
+    state: Option<WriterState<S, W>>,
+    header_state: PhantomData<H>,
+}
+
+struct WriterState<S, W> {
+    is_initialised: bool,
     is_finished: bool,
-    wtr: W,
+    // A failure may have left the stream mid-record, so nothing more is written
+    is_failed: bool,
+    header: Header,
+    wtr: RecordSink<W>,
     ser: S,
-    header_state: PhantomData<H>,
-    depth: Vec<(u16, RecordId)>,
+    depth: Vec<(u64, RecordId)>,
+    position: u64,
+    record_span: Option<RecordSpan>,
+    // User records written, excluding digest records
+    records: u64,
+    trailer: Option<Trailer>,
+    integrity: Option<IntegrityWriter>,
+    #[cfg(feature = "encryption")]
+    encryption: Option<EncryptionOptions>,
+    on_drop_error: Option<DropHook>,
+    // Reused by `write_record_fn`
+    payload: Vec<u8>,
 }
 
-impl<S, W, H> MsrfWriter<S, W, H> {
+impl<S: RawSerialiser, W: Write, H> MsrfWriter<S, W, H> {
     #[must_use]
     pub fn builder() -> MsrfWriterBuilder {
         MsrfWriterBuilder::new()
     }
+
+    fn state(&self) -> &WriterState<S, W> {
+        self.state.as_ref().expect("writer state taken")
+    }
+
+    fn state_mut(&mut self) -> &mut WriterState<S, W> {
+        self.state.as_mut().expect("writer state taken")
+    }
+
+    /// Format version written to the header.
+    pub fn version(&self) -> u16 {
+        self.state().header.version
+    }
+
+    /// Header written (or to be written) at the start of the stream.
+    pub fn header(&self) -> &Header {
+        &self.state().header
+    }
+
+    /// Returns the inner writer without finishing the stream, e.g. to append to it later.
+    pub fn into_inner_unfinished(mut self) -> W {
+        self.state.take().expect("writer state taken").wtr.into_inner()
+    }
 }
 
 impl<S: RawSerialiser, W: Write> MsrfWriter<S, W, HeaderUninit> {
-    fn new(wtr: W, ser: S) -> MsrfWriter<S, W, HeaderUninit> {
-        MsrfWriter {
+    fn new(wtr: W, ser: S, builder: MsrfWriterBuilder) -> MsrfWriter<S, W, HeaderUninit> {
+        let trailer = builder.header.declares(Features::TRAILER);
+        let integrity = builder.integrity;
+        let state = WriterState {
+            is_initialised: false,
             is_finished: false,
-            wtr,
+            is_failed: false,
+            header: builder.header,
+            wtr: RecordSink::new(wtr, trailer || integrity.is_some()),
             ser,
-            header_state: PhantomData,
             depth: Vec::new(),
+            position: 0,
+            record_span: None,
+            records: 0,
+            trailer: trailer.then(Trailer::default),
+            integrity: integrity.map(IntegrityWriter::new),
+            #[cfg(feature = "encryption")]
+            encryption: builder.encryption,
+            on_drop_error: builder.on_drop_error,
+            payload: Vec::new(),
+        };
+        MsrfWriter {
+            state: Some(state),
+            header_state: PhantomData,
         }
     }
 
     pub fn initialise(mut self) -> Result<MsrfWriter<S, W, HeaderInit>, IoError<ParserError>> {
-        let header = Header::new(CURRENT_VERSION);
-        codec::write_header(&mut self.wtr, &header)?;
+        let mut state = self.state.take().expect("writer state taken");
+        state.wtr.begin();
+        let mut wtr = CountingWriter::new(&mut state.wtr);
+        codec::write_header(&mut wtr, &state.header)?;
+        state.position += wtr.count();
+        state.wtr.end()?;
+        state.is_initialised = true;
         Ok(MsrfWriter {
-            is_finished: self.is_finished,
-            wtr: self.wtr,
-            ser: self.ser,
+            state: Some(state),
             header_state: PhantomData,
-            depth: Vec::new(),
         })
     }
 }
 
-impl<S: RawSerialiser, W: Write> MsrfWriter<S, W, HeaderInit> {
+impl<S: RawSerialiser, W: Write> WriterState<S, W> {
     fn update(&mut self, meta: &RecordMeta) {
         if let Some(count) = meta.contained()
             && count > 0
@@ -114,82 +266,392 @@ impl<S: RawSerialiser, W: Write> MsrfWriter<S, W, HeaderInit> {
         }
     }
 
+    fn check_writable(&self) -> Result<(), IoError<ParserError>> {
+        if self.is_failed {
+            Err(IoError::Parser(ParserError::Poisoned))
+        } else if self.is_finished {
+            Err(IoError::Parser(ParserError::IsEos))
+        } else {
+            Ok(())
+        }
+    }
+
+    // Fails the writer if any of the failed record reached the inner writer
     fn write_record_impl(
         &mut self,
-        user_data: impl IntoData<S, W>,
+        user_data: impl IntoData<S, RecordSink<W>>,
+        meta: RecordMeta,
+    ) -> Result<(), IoError<ParserError>> {
+        self.check_writable()?;
+        let res = self.write_record_unchecked(user_data, meta);
+        if res.is_err() && self.wtr.is_emitted() {
+            self.is_failed = true;
+        }
+        res
+    }
+
+    fn write_record_unchecked(
+        &mut self,
+        user_data: impl IntoData<S, RecordSink<W>>,
         meta: RecordMeta,
     ) -> Result<(), IoError<ParserError>> {
+        // Would be read as a digest record
+        if self.integrity.is_some()
+            && meta.contained().is_none()
+            && RecordId::from(meta) == DIGEST_RECORD
+        {
+            return Err(IoError::Parser(ParserError::Reserved(meta.into())));
+        }
+
+        let meta = self.payload_meta(meta);
+
+        self.wtr.begin();
+        let mut wtr = CountingWriter::new(&mut self.wtr);
+        self.ser.write_meta(meta, &mut wtr)?;
+        let meta_len = wtr.count();
+        // Payload length is trusted to match `meta`, as `W` is passed to `user_data` directly
+        self.write_payload(user_data, &meta)?;
+        self.wtr.write_all(&[0u8])?;
+        self.wtr.end()?;
+
+        // Only updated once written, so a failed record (with its scratch discarded) leaves no
+        // trace in the depth, trailer or digests
+        let meta_offset = self.position;
+        let payload = meta_offset + meta_len;
+        self.position = payload + meta.len() + 1;
         self.update(&meta);
+        if let Some(trailer) = &mut self.trailer {
+            trailer.update(&meta, self.depth.len());
+        }
+        self.records += 1;
+        self.record_span = Some(RecordSpan {
+            meta: meta_offset,
+            payload,
+            end: payload + meta.len(),
+        });
 
-        self.ser.write_meta(meta, &mut self.wtr)?;
-        user_data.encode_into(&mut self.wtr, &self.ser, meta.source_id())?;
+        if let Some(integrity) = &mut self.integrity {
+            integrity.pending += 1;
+            if integrity.is_due(self.depth.len()) {
+                self.write_digest(false)?;
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    fn payload_meta(&self, meta: RecordMeta) -> RecordMeta {
+        meta
+    }
+
+    // Encrypted payloads are longer than encoded
+    #[cfg(feature = "encryption")]
+    fn payload_meta(&self, mut meta: RecordMeta) -> RecordMeta {
+        if self.key(meta.source_id()).is_some() {
+            meta.length += encryption::OVERHEAD;
+        }
+        meta
+    }
+
+    #[cfg(feature = "encryption")]
+    fn key(&self, source_id: u64) -> Option<encryption::Key> {
+        self.encryption.as_ref()?.get(source_id).copied()
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    fn write_payload(
+        &mut self,
+        user_data: impl IntoData<S, RecordSink<W>>,
+        meta: &RecordMeta,
+    ) -> Result<(), IoError<ParserError>> {
+        user_data.encode_into(&mut self.wtr, &self.ser, meta.source_id())
+    }
+
+    // Encrypted payloads are held in full before encryption, so their length is checked
+    #[cfg(feature = "encryption")]
+    fn write_payload(
+        &mut self,
+        user_data: impl IntoData<S, RecordSink<W>>,
+        meta: &RecordMeta,
+    ) -> Result<(), IoError<ParserError>> {
+        let Some(key) = self.key(meta.source_id()) else {
+            return user_data.encode_into(&mut self.wtr, &self.ser, meta.source_id());
+        };
+
+        let offset = self.wtr.hold();
+        let res = user_data.encode_into(&mut self.wtr, &self.ser, meta.source_id());
+        let plaintext = self.wtr.release(offset);
+        res?;
+        if plaintext.len() as u64 + encryption::OVERHEAD != meta.len() {
+            return Err(IoError::Parser(ParserError::Length(plaintext.len() as u64)));
+        }
+        self.wtr
+            .write_all(&encryption::encrypt(&key, meta, self.records, &plaintext))?;
+        Ok(())
+    }
+
+    // Digest records are not user records, so bypass `update`, the trailer and `record_span`
+    fn write_digest(&mut self, is_final: bool) -> Result<(), IoError<ParserError>> {
+        let Some(integrity) = &mut self.integrity else {
+            return Ok(());
+        };
+        let meta = integrity.meta(is_final);
+
+        self.wtr.begin();
+        let mut wtr = CountingWriter::new(&mut self.wtr);
+        self.ser.write_meta(meta, &mut wtr)?;
+        let meta_len = wtr.count();
+
+        // SAFETY: The digest is enabled alongside integrity
+        let payload = integrity.payload(self.wtr.digest_so_far().unwrap(), is_final);
+        self.wtr.write_all(&payload)?;
         self.wtr.write_all(&[0u8])?;
+        self.wtr.end()?;
+        integrity.pending = 0;
+        self.position += meta_len + meta.len() + 1;
+        Ok(())
+    }
+
+    // Not retried on failure, as the stream may be left mid-record
+    fn finish(&mut self) -> Result<(), IoError<ParserError>> {
+        if self.is_failed {
+            return Err(IoError::Parser(ParserError::Poisoned));
+        } else if self.is_finished {
+            return Ok(());
+        }
+
+        self.is_finished = true;
+        let res = self.finish_unchecked();
+        self.is_failed = res.is_err();
+        res
+    }
+
+    fn finish_unchecked(&mut self) -> Result<(), IoError<ParserError>> {
+        self.write_digest(true)?;
+        self.wtr.begin();
+        let mut wtr = CountingWriter::new(&mut self.wtr);
+        let res = self.ser.write_meta(RecordMeta::new_eos(), &mut wtr);
+        self.position += wtr.count();
+        self.record_span = None;
+        res?;
 
+        if let Some(mut trailer) = self.trailer.take() {
+            // SAFETY: The digest is enabled alongside the trailer
+            trailer.digest = self.wtr.finalize_digest().unwrap();
+            let mut wtr = CountingWriter::new(&mut self.wtr);
+            let res = trailer.write_into(&mut wtr);
+            self.position += wtr.count();
+            res?;
+        }
+        self.wtr.end()?;
+        self.wtr.flush()?;
         Ok(())
     }
+}
 
+impl<S: RawSerialiser, W: Write> MsrfWriter<S, W, HeaderInit> {
     pub fn write_record(
         &mut self,
-        user_data: impl IntoData<S, W> + IntoMetadata<S>,
-        source_id: u16,
+        user_data: impl IntoData<S, RecordSink<W>> + IntoMetadata<S>,
+        source_id: u64,
     ) -> Result<(), IoError<ParserError>> {
-        let meta = user_data.meta(&self.ser, source_id);
+        let state = self.state_mut();
+        let meta = user_data.meta(&state.ser, source_id);
 
-        if self.is_finished {
-            return Err(IoError::Parser(ParserError::IsEos));
-        } else if meta.is_eos() {
+        if meta.is_eos() {
             // TODO: Better handling of EoS RecordMeta
             return Err(IoError::Parser(ParserError::UnexpectedEos));
         }
 
-        self.write_record_impl(user_data, meta)
+        state.write_record_impl(user_data, meta)
     }
 
     pub fn write_record_with(
         &mut self,
-        user_data: impl IntoData<S, W> + SizedValue<S>,
+        user_data: impl IntoData<S, RecordSink<W>>,
         id: RecordId,
     ) -> Result<(), IoError<ParserError>> {
-        if self.is_finished {
-            return Err(IoError::Parser(ParserError::IsEos));
+        let state = self.state_mut();
+        let meta = id.into_meta(user_data.encoded_len(&state.ser) as u64);
+        state.write_record_impl(user_data, meta)
+    }
+
+    /// Writes a record whose payload is serialised by `f` into a reused buffer, deriving its length
+    /// from the bytes written rather than [`SizedValue`].
+    pub fn write_record_fn(
+        &mut self,
+        id: RecordId,
+        f: impl FnOnce(&mut Vec<u8>) -> std::io::Result<()>,
+    ) -> Result<(), IoError<ParserError>> {
+        if id.is_eos() {
+            return Err(IoError::Parser(ParserError::UnexpectedEos));
         }
 
-        let meta = id.into_meta(user_data.encoded_len(&self.ser) as u64);
-        self.write_record_impl(user_data, meta)
+        let state = self.state_mut();
+        let mut payload = std::mem::take(&mut state.payload);
+        payload.clear();
+        let res = f(&mut payload).map_err(IoError::from).and_then(|()| {
+            let meta = id.into_meta(payload.len() as u64);
+            state.write_record_impl(Bytes(&payload), meta)
+        });
+        state.payload = payload;
+        res
     }
 
-    pub fn write_container(&mut self, user_data: impl IntoData<S, W> + IntoMetadata<S>, source_id: u16, length: u16) -> Result<(), IoError<ParserError>> {
-        let mut meta = user_data.meta(&self.ser, source_id);
+    /// Writes `meta` as is (including an empty container), used to copy records between streams.
+    pub(crate) fn write_raw(
+        &mut self,
+        user_data: impl IntoData<S, RecordSink<W>>,
+        meta: RecordMeta,
+    ) -> Result<(), IoError<ParserError>> {
+        if meta.is_eos() {
+            return Err(IoError::Parser(ParserError::UnexpectedEos));
+        }
+
+        self.state_mut().write_record_impl(user_data, meta)
+    }
+
+    /// Writes a record read by [`crate::reader::MsrfReader::read_owned`] (or constructed), keeping
+    /// its container count. Its parents are not checked against those being written.
+    pub fn write_owned(&mut self, record: &OwnedRecord) -> Result<(), IoError<ParserError>> {
+        self.write_raw(Bytes(&record.payload), record.meta)
+    }
+
+    pub fn write_container(&mut self, user_data: impl IntoData<S, RecordSink<W>> + IntoMetadata<S>, source_id: u64, length: u64) -> Result<(), IoError<ParserError>> {
+        let state = self.state_mut();
+        let mut meta = user_data.meta(&state.ser, source_id);
         meta.contained = Some(length);
 
-        if self.is_finished {
-            return Err(IoError::Parser(ParserError::IsEos));
-        } else if meta.is_eos() {
+        if meta.is_eos() {
             // TODO: Better handling of EoS RecordMeta
             return Err(IoError::Parser(ParserError::UnexpectedEos));
         }
 
-        self.write_record_impl(user_data, meta)
+        state.write_record_impl(user_data, meta)
+    }
+
+    /// Writes each of `records` from `source_id`, returning the number written.
+    pub fn write_records<T>(
+        &mut self,
+        source_id: u64,
+        records: impl IntoIterator<Item = T>,
+    ) -> Result<u64, BatchError>
+    where
+        T: IntoData<S, RecordSink<W>> + IntoMetadata<S>,
+    {
+        let mut written = 0;
+        for record in records {
+            self.write_record(record, source_id)
+                .map_err(|e| BatchError::new(written, e))?;
+            written += 1;
+        }
+        Ok(written)
+    }
+
+    /// Writes `parent` as a container of `children` from `source_id`, returning the number of
+    /// records written (including the container).
+    ///
+    /// The child count is that of an exact size hint (e.g. [`ExactSizeIterator`]), otherwise the
+    /// children are buffered first. Without children, `parent` is written as a plain record.
+    ///
+    /// A size hint that overstates the children leaves the container open, so the writer is
+    /// poisoned and the stream cannot be finished. One that understates them is an error once the
+    /// container is written, leaving the surplus children unwritten.
+    pub fn write_container_from_iter<P, T>(
+        &mut self,
+        parent: P,
+        source_id: u64,
+        children: impl IntoIterator<Item = T>,
+    ) -> Result<u64, BatchError>
+    where
+        P: IntoData<S, RecordSink<W>> + IntoMetadata<S>,
+        T: IntoData<S, RecordSink<W>> + IntoMetadata<S>,
+    {
+        let children = children.into_iter();
+        match children.size_hint() {
+            (lower, Some(upper)) if lower == upper => {
+                self.write_children(parent, source_id, lower, children)
+            }
+            _ => {
+                let children: Vec<_> = children.collect();
+                self.write_children(parent, source_id, children.len(), children.into_iter())
+            }
+        }
     }
 
-    // TODO: Call on drop()
-    // TODO: Make impossible to be `self.is_finished` early (consume self)
-    pub fn finish(&mut self) -> Result<(), IoError<ParserError>> {
-        if self.is_finished {
-            return Err(IoError::Parser(ParserError::IsEos));
+    fn write_children<P, T>(
+        &mut self,
+        parent: P,
+        source_id: u64,
+        count: usize,
+        mut children: impl Iterator<Item = T>,
+    ) -> Result<u64, BatchError>
+    where
+        P: IntoData<S, RecordSink<W>> + IntoMetadata<S>,
+        T: IntoData<S, RecordSink<W>> + IntoMetadata<S>,
+    {
+        let res = if count > 0 {
+            self.write_container(parent, source_id, count as u64)
+        } else {
+            self.write_record(parent, source_id)
+        };
+        res.map_err(|e| BatchError::new(0, e))?;
+        let written = self
+            .write_records(source_id, children.by_ref().take(count))
+            .map_err(|e| BatchError::new(e.written() + 1, e.into_inner()))?;
+        // The size hint overstated the children, leaving the container short
+        if written < count as u64 {
+            self.state_mut().is_failed = true;
+            let error = IoError::Parser(ParserError::Length(written));
+            return Err(BatchError::new(written + 1, error));
         }
+        // The size hint understated the children, which would otherwise be dropped
+        if children.next().is_some() {
+            let error = IoError::Parser(ParserError::Length(written));
+            return Err(BatchError::new(written + 1, error));
+        }
+        Ok(written + 1)
+    }
 
-        self.ser.write_meta(RecordMeta::new_eos(), &mut self.wtr)?;
+    /// Writes EoS (preceded and followed by any digest or trailer declared by the header) and
+    /// flushes, returning the inner writer.
+    pub fn finish(mut self) -> Result<W, IoError<ParserError>> {
+        let mut state = self.state.take().expect("writer state taken");
+        state.finish()?;
+        Ok(state.wtr.into_inner())
+    }
+
+    // Finishes without giving up the writer, after which writes fail with `IsEos`
+    pub(crate) fn finish_in_place(&mut self) -> Result<(), IoError<ParserError>> {
+        self.state_mut().finish()
+    }
+
+    /// Offsets of the most recently written record.
+    pub fn record_span(&self) -> Option<RecordSpan> {
+        self.state().record_span
+    }
+
+    /// Total bytes written, including the header, guards and EoS.
+    pub fn position(&self) -> u64 {
+        self.state().position
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.state().wtr.get_ref()
+    }
+
+    pub fn flush(&mut self) -> Result<(), IoError<ParserError>> {
+        self.state_mut().wtr.flush()?;
         Ok(())
     }
 
     pub fn current_parent(&self) -> Option<RecordId> {
-        self.depth.last().map(|(_, id)| id).copied()
+        self.state().depth.last().map(|(_, id)| id).copied()
     }
 
     // Top down
     pub fn parents(&self) -> impl DoubleEndedIterator<Item = RecordId> {
-        self.depth.iter().rev().map(|(_, id)| id).copied()
+        self.state().depth.iter().rev().map(|(_, id)| id).copied()
     }
 
     // pub fn write_record_2<Ser, V>(
@@ -216,6 +678,49 @@ impl<S: RawSerialiser, W: Write> MsrfWriter<S, W, HeaderInit> {
     // }
 }
 
+// Payload already encoded, e.g. by `write_record_fn`
+#[derive(Debug)]
+struct Bytes<'a>(&'a [u8]);
+
+impl<S> SizedValue<S> for Bytes<'_> {
+    fn encoded_len(&self, _ser: &S) -> usize {
+        self.0.len()
+    }
+}
+
+impl<S: RawSerialiser, W: Write> IntoData<S, W> for Bytes<'_> {
+    fn encode_into(&self, wtr: &mut W, _ser: &S, _source_id: u64) -> Result<(), IoError<ParserError>> {
+        wtr.write_all(self.0)?;
+        Ok(())
+    }
+}
+
+// Finishing while unwinding, after a failed write or within a container could mark a partially
+// written record or container as complete
+impl<S: RawSerialiser, W: Write, H> Drop for MsrfWriter<S, W, H> {
+    fn drop(&mut self) {
+        let Some(state) = &mut self.state else {
+            return;
+        };
+        if !state.is_initialised || state.is_finished || std::thread::panicking() {
+            return;
+        }
+
+        let res = if state.is_failed {
+            Err(IoError::Parser(ParserError::Poisoned))
+        } else if !state.depth.is_empty() {
+            Err(IoError::Parser(ParserError::UnexpectedEos))
+        } else {
+            state.finish()
+        };
+        if let Err(e) = res
+            && let Some(on_drop_error) = &state.on_drop_error
+        {
+            on_drop_error(&e);
+        }
+    }
+}
+
 //
 // let registrar = SourceRegistrar::new();
 // let msrf_ext_id = registrar.register_root(MsrfExtWriter::name());
@@ -228,4 +733,568 @@ impl<S: RawSerialiser, W: Write> MsrfWriter<S, W, HeaderInit> {
 // msrf_wtr.write_record(msrf_ext_wtr, record)?;
 // msrf_wtr.write_record(msrf_ext_wtr, records[..])?;
 // msrf_wtr.write_container(custom_wtr, record, records.iter().length())?;
-// msrf_wtr.write_record_from_iter(custom_wtr, records.iter())?;
+// msrf_wtr.write_records(custom_wtr, records.iter())?;
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use crate::{
+        EXTENDED_HEADER_VERSION, Features, Header, IntoMetadata, RecordId, RecordSpan,
+        codec::{DesOptions, IntoData, RawSerialiser, constants::HEADER_LEN},
+        error::{BuildError, IoError, ParserError},
+        integrity::{DIGEST_RECORD, IntegrityOptions},
+        io::SizedValue,
+        rotate::test::Value,
+        writer::MsrfWriterBuilder,
+    };
+
+
+    #[test]
+    fn write_record_span() {
+        let mut wtr = MsrfWriterBuilder::new()
+            .build(Vec::new())
+            .expect("unsupported version")
+            .initialise()
+            .expect("failed to initialise");
+        assert_eq!(wtr.position(), HEADER_LEN as u64);
+        assert_eq!(wtr.record_span(), None);
+
+        // Source: 2, Type: 2, Length: PV(1), Contained: 2
+        wtr.write_container(Value(0), 1, 1).expect("failed write");
+        assert_eq!(
+            wtr.record_span(),
+            Some(RecordSpan {
+                meta: HEADER_LEN as u64,
+                payload: HEADER_LEN as u64 + 7,
+                end: HEADER_LEN as u64 + 8,
+            })
+        );
+
+        // Source: 2, Type: 2, Length: PV(1)
+        let offset = wtr.position();
+        wtr.write_record(Value(1), 1).expect("failed write");
+        let span = wtr.record_span().expect("missing span");
+        assert_eq!(span.meta_offset(), offset);
+        assert_eq!(span.payload_offset(), offset + 5);
+        assert_eq!(span.payload_len(), 1);
+        assert_eq!(wtr.position(), span.payload_end() + 1);
+
+        // EoS: 2
+        let position = wtr.position();
+        let data = wtr.finish().expect("failed finish");
+        assert_eq!(position + 2, data.len() as u64);
+    }
+
+    #[test]
+    fn write_reserved() {
+        let mut wtr = MsrfWriterBuilder::new()
+            .version(EXTENDED_HEADER_VERSION)
+            .integrity(IntegrityOptions::new())
+            .build(Vec::new())
+            .expect("unsupported version")
+            .initialise()
+            .expect("failed to initialise");
+
+        let position = wtr.position();
+        assert!(matches!(
+            wtr.write_record_with(Value(0), DIGEST_RECORD),
+            Err(IoError::Parser(ParserError::Reserved(e))) if e == DIGEST_RECORD
+        ));
+        assert_eq!(wtr.position(), position);
+
+        // Only reserved by streams with digest records
+        let mut wtr = MsrfWriterBuilder::new()
+            .build(Vec::new())
+            .expect("unsupported version")
+            .initialise()
+            .expect("failed to initialise");
+        wtr.write_record_with(Value(0), DIGEST_RECORD)
+            .expect("failed write");
+    }
+
+    #[test]
+    fn write_empty_container() {
+        let mut wtr = MsrfWriterBuilder::new()
+            .build(Vec::new())
+            .expect("unsupported version")
+            .initialise()
+            .expect("failed to initialise");
+
+        wtr.write_container(Value(0), 1, 0).expect("failed write");
+        wtr.write_record(Value(1), 1).expect("failed write");
+        assert_eq!(wtr.current_parent(), None);
+
+        // Source: 2, Type: 2, Length: PV(1), Contained: 2, Value: 1, Guard: 1
+        let data = &wtr.get_ref()[HEADER_LEN..];
+        assert_eq!(data[..9], [1, 0, 1, 0x80, 0b11, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn write_record_fn() {
+        use std::io::Write;
+
+        let mut wtr = MsrfWriterBuilder::new()
+            .build(Vec::new())
+            .expect("unsupported version")
+            .initialise()
+            .expect("failed to initialise");
+
+        wtr.write_record_fn(RecordId::new(1, 2), |w| w.write_all(&[1, 2, 3]))
+            .expect("failed write");
+        let err = wtr
+            .write_record_fn(RecordId::new(1, 2), |_| Err(std::io::Error::other("failed")))
+            .expect_err("succeeded write");
+        assert!(matches!(err, IoError::Io(_)));
+
+        // Source: 2, Type: 2, Length: PV(1), Value: 3, Guard: 1
+        let data = &wtr.get_ref()[HEADER_LEN..];
+        assert_eq!(data, [1, 0, 2, 0, 0b111, 1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn write_records_from_iter() {
+        let new_writer = || {
+            MsrfWriterBuilder::new()
+                .build(Vec::new())
+                .expect("unsupported version")
+                .initialise()
+                .expect("failed to initialise")
+        };
+
+        let mut expected = new_writer();
+        expected.write_container(Value(0), 1, 2).expect("failed write");
+        expected.write_record(Value(1), 1).expect("failed write");
+        expected.write_record(Value(2), 1).expect("failed write");
+        expected.write_record(Value(3), 1).expect("failed write");
+
+        // Exact and inexact size hints
+        let mut wtr = new_writer();
+        let written = wtr
+            .write_container_from_iter(Value(0), 1, (1..3).map(Value))
+            .expect("failed write");
+        assert_eq!(written, 3);
+        let written = wtr.write_records(1, [Value(3)]).expect("failed write");
+        assert_eq!(written, 1);
+        assert_eq!(wtr.get_ref(), expected.get_ref());
+
+        let mut wtr = new_writer();
+        let children = (1..3).filter(|_| true).map(Value);
+        let written = wtr
+            .write_container_from_iter(Value(0), 1, children)
+            .expect("failed write");
+        assert_eq!(written, 3);
+        assert_eq!(wtr.current_parent(), None);
+
+        // Size hints overstating and understating the children
+        struct Hinted<I>(I, usize);
+
+        impl<I: Iterator> Iterator for Hinted<I> {
+            type Item = I::Item;
+
+            fn next(&mut self) -> Option<Self::Item> {
+                self.0.next()
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                (self.1, Some(self.1))
+            }
+        }
+
+        let mut wtr = new_writer();
+        let err = wtr
+            .write_container_from_iter(Value(0), 1, Hinted((1..3).map(Value), 3))
+            .expect_err("succeeded write");
+        assert_eq!(err.written(), 3);
+        assert!(matches!(
+            err.into_inner(),
+            IoError::Parser(ParserError::Length(2))
+        ));
+        assert!(matches!(
+            wtr.write_record(Value(3), 1),
+            Err(IoError::Parser(ParserError::Poisoned))
+        ));
+
+        let mut wtr = new_writer();
+        let err = wtr
+            .write_container_from_iter(Value(0), 1, Hinted((1..4).map(Value), 2))
+            .expect_err("succeeded write");
+        assert_eq!(err.written(), 3);
+        assert!(matches!(
+            err.into_inner(),
+            IoError::Parser(ParserError::Length(2))
+        ));
+        assert_eq!(wtr.current_parent(), None);
+
+        // Only room for the header and 2 records
+        let mut buf = [0; HEADER_LEN + 14];
+        let mut wtr = MsrfWriterBuilder::new()
+            .build(buf.as_mut_slice())
+            .expect("unsupported version")
+            .initialise()
+            .expect("failed to initialise");
+        let err = wtr
+            .write_records(1, (0..3).map(Value))
+            .expect_err("succeeded write");
+        assert_eq!(err.written(), 2);
+        assert!(matches!(err.into_inner(), IoError::Io(_)));
+    }
+
+    #[test]
+    fn build_version() {
+        assert!(matches!(
+            MsrfWriterBuilder::new().version(3).build(Vec::new()),
+            Err(BuildError::Unsupported(3))
+        ));
+
+        let wtr = MsrfWriterBuilder::new()
+            .build(Vec::new())
+            .expect("unsupported version")
+            .initialise()
+            .expect("failed to initialise");
+        assert_eq!(wtr.version(), 0);
+        assert_eq!(wtr.get_ref()[4..6], 0_u16.to_le_bytes());
+    }
+
+    #[cfg(feature = "reader")]
+    #[test]
+    fn write_v1_wide_ids() {
+        use std::io::{Cursor, Read};
+
+        use crate::reader::MsrfReaderBuilder;
+
+        let mut wtr = MsrfWriterBuilder::new()
+            .version(1)
+            .build(Vec::new())
+            .expect("unsupported version")
+            .initialise()
+            .expect("failed to initialise");
+        assert_eq!(wtr.version(), 1);
+
+        wtr.write_container(Value(0), 0x1_0000, 1)
+            .expect("failed write");
+        wtr.write_record(Value(1), 0x1_0000).expect("failed write");
+        assert!(matches!(
+            MsrfWriterBuilder::new()
+                .build(Vec::new())
+                .expect("unsupported version")
+                .initialise()
+                .expect("failed to initialise")
+                .write_record(Value(0), 0x1_0000),
+            Err(IoError::Parser(ParserError::Width(0x1_0000)))
+        ));
+        let data = wtr.finish().expect("failed finish");
+
+        let mut rdr = MsrfReaderBuilder::new()
+            .build(Cursor::new(data))
+            .expect("failed to build");
+        assert_eq!(rdr.version(), 1);
+        for value in 0..2 {
+            let (id, mut user_rdr) = rdr.read_record().expect("failed to parse record");
+            assert_eq!(id, RecordId::new(0x1_0000, 1));
+            let mut user_buf = Vec::new();
+            user_rdr.read_to_end(&mut user_buf).expect("io fail");
+            assert_eq!(user_buf, [value]);
+        }
+        assert!(rdr.read_record().is_err());
+        assert!(rdr.is_finished());
+    }
+
+    #[cfg(feature = "reader")]
+    #[test]
+    fn write_extended_header() {
+        use std::io::Cursor;
+
+        use crate::{error::ErrorKind, reader::MsrfReaderBuilder};
+
+        let header = Header::new(EXTENDED_HEADER_VERSION)
+            .with_required(Features::from_bits(1))
+            .with_producer("test");
+        assert!(matches!(
+            MsrfWriterBuilder::new()
+                .header(header.clone())
+                .version(1)
+                .build(Vec::new()),
+            Err(BuildError::Extended(1))
+        ));
+
+        let mut wtr = MsrfWriterBuilder::new()
+            .header(header.clone())
+            .build(Vec::new())
+            .expect("unsupported version")
+            .initialise()
+            .expect("failed to initialise");
+        wtr.write_record(Value(0), 1).expect("failed write");
+        let data = wtr.finish().expect("failed finish");
+
+        assert!(matches!(
+            MsrfReaderBuilder::new().build(Cursor::new(data.clone())),
+            Err(BuildError::Read(e)) if e.kind() == ErrorKind::Unsupported
+        ));
+
+        let mut rdr = MsrfReaderBuilder::new()
+            .options(DesOptions::new().supported_features(Features::from_bits(1)))
+            .build(Cursor::new(data))
+            .expect("failed to build");
+        assert_eq!(rdr.header(), Some(&header));
+        let (_, user_rdr) = rdr.read_record().expect("failed to parse record");
+        assert_eq!(user_rdr.len(), 1);
+    }
+
+    // Fails after encoding part of its payload
+    #[derive(Debug)]
+    struct Failing;
+
+    impl crate::ConstAssignedId for Failing {
+        const TYPE_ID: u64 = 2;
+    }
+
+    impl<S> SizedValue<S> for Failing {
+        fn encoded_len(&self, _ser: &S) -> usize {
+            2
+        }
+    }
+
+    impl<S: RawSerialiser> IntoMetadata<S> for Failing {}
+
+    impl<S: RawSerialiser, W: Write> IntoData<S, W> for Failing {
+        fn encode_into(
+            &self,
+            wtr: &mut W,
+            _ser: &S,
+            _source_id: u64,
+        ) -> Result<(), IoError<ParserError>> {
+            wtr.write_all(&[0])?;
+            Err(IoError::Io(std::io::Error::other("failed")))
+        }
+    }
+
+    #[cfg(feature = "reader")]
+    #[test]
+    fn write_failed_record() {
+        use std::io::Cursor;
+
+        use crate::{OwnedRecord, codec::Strictness, reader::MsrfReaderBuilder};
+
+        let mut wtr = MsrfWriterBuilder::new()
+            .header(Header::new(EXTENDED_HEADER_VERSION).with_optional(Features::TRAILER))
+            .integrity(IntegrityOptions::new().interval(1))
+            .build(Vec::new())
+            .expect("unsupported version")
+            .initialise()
+            .expect("failed to initialise");
+        wtr.write_container(Value(0), 1, 2).expect("failed write");
+        wtr.write_record(Value(1), 1).expect("failed write");
+        let position = wtr.position();
+        wtr.write_record(Failing, 1).expect_err("succeeded write");
+        assert_eq!(wtr.position(), position);
+        wtr.write_record(Value(2), 1).expect("failed write");
+        wtr.write_record(Failing, 1).expect_err("succeeded write");
+        wtr.write_record(Value(3), 1).expect("failed write");
+        let data = wtr.finish().expect("failed finish");
+
+        let mut rdr = MsrfReaderBuilder::new()
+            .options(DesOptions::new().strictness(Strictness::Strict))
+            .build(Cursor::new(data))
+            .expect("failed to build")
+            .into_records();
+        let records = rdr
+            .by_ref()
+            .collect::<Result<Vec<_>, _>>()
+            .expect("failed read");
+        let parents: Vec<_> = records.iter().map(OwnedRecord::parents).collect();
+        let container = [RecordId::new(1, 1)];
+        assert_eq!(parents, [&[][..], &container, &container, &[]]);
+        let payloads: Vec<_> = records.iter().map(OwnedRecord::payload).collect();
+        assert_eq!(payloads, [[0], [1], [2], [3]]);
+        let trailer = rdr
+            .into_inner()
+            .trailer()
+            .cloned()
+            .expect("missing trailer");
+        assert_eq!(trailer.records(), 4);
+    }
+
+    #[cfg(feature = "reader")]
+    #[test]
+    fn write_owned_roundtrip() {
+        use std::io::Cursor;
+
+        use crate::{OwnedRecord, reader::MsrfReaderBuilder};
+
+        let mut wtr = MsrfWriterBuilder::new()
+            .build(Vec::new())
+            .expect("unsupported version")
+            .initialise()
+            .expect("failed to initialise");
+        wtr.write_container(Value(0), 1, 2).expect("failed write");
+        wtr.write_container(Value(1), 1, 0).expect("failed write");
+        wtr.write_record(Value(2), 1).expect("failed write");
+        wtr.write_owned(&OwnedRecord::new(RecordId::new(2, 3), vec![4, 5]))
+            .expect("failed write");
+        let data = wtr.finish().expect("failed finish");
+
+        let records = MsrfReaderBuilder::new()
+            .build(Cursor::new(data.clone()))
+            .expect("failed to build")
+            .into_records()
+            .collect::<Result<Vec<_>, _>>()
+            .expect("failed read");
+        let parents: Vec<_> = records.iter().map(OwnedRecord::parents).collect();
+        let container = [RecordId::new(1, 1)];
+        assert_eq!(parents, [&[][..], &container, &container, &[]]);
+        assert_eq!(records[0].meta().contained(), Some(2));
+        assert_eq!(records[3].payload(), [4, 5]);
+
+        let mut wtr = MsrfWriterBuilder::new()
+            .build(Vec::new())
+            .expect("unsupported version")
+            .initialise()
+            .expect("failed to initialise");
+        for record in &records {
+            wtr.write_owned(record).expect("failed write");
+        }
+        assert_eq!(wtr.finish().expect("failed finish"), data);
+    }
+
+    #[test]
+    fn write_single_write() {
+        use std::io::{IoSlice, Write};
+
+        use crate::OwnedRecord;
+
+        // Counts the writes (vectored or not) accepted
+        #[derive(Default)]
+        struct WriteCounter {
+            data: Vec<u8>,
+            writes: usize,
+        }
+
+        impl Write for WriteCounter {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.writes += 1;
+                self.data.write(buf)
+            }
+
+            fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> std::io::Result<usize> {
+                self.writes += 1;
+                self.data.write_vectored(bufs)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut wtr = MsrfWriterBuilder::new()
+            .build(WriteCounter::default())
+            .expect("unsupported version")
+            .initialise()
+            .expect("failed to initialise");
+        assert_eq!(wtr.get_ref().writes, 1);
+
+        wtr.write_container(Value(0), 1, 1).expect("failed write");
+        wtr.write_record(Value(1), 1).expect("failed write");
+        assert_eq!(wtr.get_ref().writes, 3);
+
+        // Meta and payload written together, followed by the guard
+        let record = OwnedRecord::new(RecordId::new(1, 2), vec![42; 16 * 1024]);
+        wtr.write_owned(&record).expect("failed write");
+        assert_eq!(wtr.get_ref().writes, 5);
+
+        let wtr = wtr.finish().expect("failed finish");
+        assert_eq!(wtr.writes, 6);
+        // Container: 9, Record: 7, Meta: 2 + 2 + PV(3), Guard: 1, EoS: 2
+        assert_eq!(wtr.data.len(), HEADER_LEN + 9 + 7 + 7 + 16 * 1024 + 1 + 2);
+    }
+
+    #[test]
+    fn write_drop_unfinished() {
+        use std::sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        };
+
+        use crate::RECORD_EOS;
+
+        let mut data = Vec::new();
+        let mut wtr = MsrfWriterBuilder::new()
+            .build(&mut data)
+            .expect("unsupported version")
+            .initialise()
+            .expect("failed to initialise");
+        wtr.write_record(Value(0), 1).expect("failed write");
+        drop(wtr);
+        assert_eq!(&data[data.len() - 2..], &RECORD_EOS.to_le_bytes());
+
+        let wtr = MsrfWriterBuilder::new()
+            .build(Vec::new())
+            .expect("unsupported version")
+            .initialise()
+            .expect("failed to initialise");
+        assert_eq!(wtr.into_inner_unfinished().len(), HEADER_LEN);
+
+        // Only room for the header
+        let errors = Arc::new(AtomicUsize::new(0));
+        let counter = errors.clone();
+        let mut buf = [0; HEADER_LEN];
+        let wtr = MsrfWriterBuilder::new()
+            .on_drop_error(move |_| {
+                counter.fetch_add(1, Ordering::Relaxed);
+            })
+            .build(buf.as_mut_slice())
+            .expect("unsupported version")
+            .initialise()
+            .expect("failed to initialise");
+        drop(wtr);
+        assert_eq!(errors.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn write_drop_failed() {
+        use std::sync::{Arc, Mutex};
+
+        use crate::{OwnedRecord, error::ErrorKind};
+
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let on_drop_error = {
+            let errors = errors.clone();
+            move |e: &IoError<ParserError>| errors.lock().unwrap().push(e.kind())
+        };
+
+        // Within a container
+        let mut data = Vec::new();
+        let mut wtr = MsrfWriterBuilder::new()
+            .on_drop_error(on_drop_error.clone())
+            .build(&mut data)
+            .expect("unsupported version")
+            .initialise()
+            .expect("failed to initialise");
+        wtr.write_container(Value(0), 1, 2).expect("failed write");
+        wtr.write_record(Value(1), 1).expect("failed write");
+        drop(wtr);
+        // Container: 9, Record: 7
+        assert_eq!(data.len(), HEADER_LEN + 9 + 7);
+
+        // Part of a record outgrowing the scratch buffer reaches the inner writer
+        let mut buf = vec![0; HEADER_LEN + 1024];
+        let mut wtr = MsrfWriterBuilder::new()
+            .on_drop_error(on_drop_error)
+            .build(buf.as_mut_slice())
+            .expect("unsupported version")
+            .initialise()
+            .expect("failed to initialise");
+        let record = OwnedRecord::new(RecordId::new(1, 2), vec![42; 16 * 1024]);
+        wtr.write_owned(&record).expect_err("succeeded write");
+        assert!(matches!(
+            wtr.write_record(Value(0), 1),
+            Err(IoError::Parser(ParserError::Poisoned))
+        ));
+        drop(wtr);
+
+        assert_eq!(
+            *errors.lock().unwrap(),
+            [ErrorKind::UnexpectedEos, ErrorKind::Poisoned]
+        );
+    }
+}